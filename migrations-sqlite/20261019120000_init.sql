@@ -0,0 +1,83 @@
+-- Add migration script here
+-- SQLite schema of the tables the crawler writes, coordinates are stored as two REAL columns
+-- instead of a POINT and JSON as TEXT
+CREATE TABLE restaurant (
+    idrestaurant INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    name TEXT,
+    lat REAL,
+    lon REAL,
+    hours TEXT,
+    address TEXT,
+    geocode_source TEXT,
+    geocode_confidence REAL,
+    source TEXT NOT NULL DEFAULT 'crous' CHECK (source IN ('crous', 'import'))
+);
+CREATE INDEX idx_restaurant_source ON restaurant(source);
+
+CREATE TABLE meal (
+    idmeal INTEGER PRIMARY KEY AUTOINCREMENT,
+    typemeal TEXT,
+    service TEXT,
+    foodies TEXT,
+    tags TEXT,
+    day TEXT,
+    idrestaurant INTEGER REFERENCES restaurant(idrestaurant) ON DELETE CASCADE
+);
+CREATE INDEX idx_meal_day ON meal(day);
+
+CREATE TABLE menu_scrape_status (
+    idstatus INTEGER PRIMARY KEY AUTOINCREMENT,
+    idrestaurant INTEGER NOT NULL REFERENCES restaurant(idrestaurant) ON DELETE CASCADE,
+    day TEXT NOT NULL,
+    run_started_at TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('ok', 'not_published', 'closed', 'parse_error', 'fetch_error')),
+    detail TEXT,
+    meal_count INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE school (
+    idschool INTEGER PRIMARY KEY AUTOINCREMENT,
+    code_uai TEXT UNIQUE,
+    long_name TEXT,
+    name TEXT,
+    lat REAL,
+    lon REAL,
+    address TEXT,
+    postcode TEXT,
+    commune TEXT,
+    phone TEXT,
+    school_type TEXT,
+    website TEXT,
+    geocode_source TEXT,
+    geocode_confidence REAL,
+    source TEXT NOT NULL DEFAULT 'onisep' CHECK (source IN ('onisep', 'import')),
+    active INTEGER NOT NULL DEFAULT 1,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    deactivated_at TEXT
+);
+
+-- categories are stored by name, SQLite has no cat_suggestions table
+CREATE TABLE suggestions_restaurant (
+    idsuggestion INTEGER PRIMARY KEY AUTOINCREMENT,
+    keyword TEXT NOT NULL,
+    folded TEXT,
+    stem TEXT,
+    idrestaurant INTEGER NOT NULL REFERENCES restaurant(idrestaurant) ON DELETE CASCADE,
+    category TEXT NOT NULL,
+    source TEXT,
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    last_seen_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (keyword, idrestaurant, category)
+);
+
+CREATE TABLE suggestion_index (
+    term TEXT NOT NULL,
+    folded TEXT NOT NULL,
+    stem TEXT NOT NULL,
+    category TEXT NOT NULL,
+    occurrences INTEGER NOT NULL,
+    restaurants INTEGER NOT NULL,
+    PRIMARY KEY (stem, category)
+);
+CREATE INDEX idx_suggestion_index_folded ON suggestion_index(folded);
@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE crawl_run (
+    idrun INTEGER PRIMARY KEY AUTOINCREMENT,
+    action TEXT NOT NULL,
+    region TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('succeeded', 'failed')),
+    fetched INTEGER NOT NULL DEFAULT 0,
+    parsed INTEGER NOT NULL DEFAULT 0,
+    inserted INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    errors INTEGER NOT NULL DEFAULT 0,
+    message TEXT
+);
+CREATE INDEX idx_crawl_run_action ON crawl_run(action, started_at);
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE menu_scrape_status(
+    idstatus serial PRIMARY KEY,
+    idrestaurant INT NOT NULL,
+    day DATE NOT NULL,
+    run_started_at TIMESTAMPTZ NOT NULL,
+    outcome TEXT NOT NULL,
+    detail TEXT,
+    meal_count INT NOT NULL DEFAULT 0,
+    CONSTRAINT fk_idrestaurant_mss FOREIGN KEY (idrestaurant) REFERENCES restaurant(idrestaurant) ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT uq_menu_scrape_status UNIQUE (idrestaurant, day, run_started_at),
+    CONSTRAINT ck_outcome_mss CHECK (outcome IN ('ok', 'not_published', 'closed', 'parse_error', 'fetch_error'))
+);
+
+CREATE INDEX idx_menu_scrape_status_day ON menu_scrape_status(day, idrestaurant);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE suggestions_restaurant ADD COLUMN folded TEXT;
+
+UPDATE suggestions_restaurant SET folded = lower(keyword) WHERE folded IS NULL;
@@ -0,0 +1,30 @@
+-- Add migration script here
+ALTER TABLE suggestions_restaurant ADD COLUMN occurrences INT NOT NULL DEFAULT 1;
+ALTER TABLE suggestions_restaurant ADD COLUMN last_seen TIMESTAMPTZ NOT NULL DEFAULT now();
+
+DELETE FROM suggestions_restaurant WHERE keyword IS NULL OR idrestaurant IS NULL OR idcat IS NULL;
+
+-- merge the existing duplicates into the oldest row
+WITH duplicates AS (
+    SELECT keyword, idrestaurant, idcat, min(idsuggestion) AS idkept, count(*) AS total
+    FROM suggestions_restaurant
+    GROUP BY keyword, idrestaurant, idcat
+    HAVING count(*) > 1
+)
+UPDATE suggestions_restaurant s SET occurrences = d.total
+FROM duplicates d
+WHERE s.idsuggestion = d.idkept;
+
+DELETE FROM suggestions_restaurant s
+USING suggestions_restaurant kept
+WHERE s.keyword = kept.keyword
+    AND s.idrestaurant = kept.idrestaurant
+    AND s.idcat = kept.idcat
+    AND s.idsuggestion > kept.idsuggestion;
+
+ALTER TABLE suggestions_restaurant ALTER COLUMN keyword SET NOT NULL;
+ALTER TABLE suggestions_restaurant ALTER COLUMN idrestaurant SET NOT NULL;
+ALTER TABLE suggestions_restaurant ALTER COLUMN idcat SET NOT NULL;
+ALTER TABLE suggestions_restaurant ADD CONSTRAINT uq_keyword_sr UNIQUE (keyword, idrestaurant, idcat);
+
+CREATE INDEX idx_suggestions_restaurant_occurrences ON suggestions_restaurant(idcat, occurrences DESC);
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+-- keywords used to be filed with hardcoded ids that didn't match cat_suggestions:
+-- restaurant keywords were stored under 2 ('food') and food keywords under 3 ('meal')
+ALTER TABLE suggestions_restaurant DROP CONSTRAINT uq_keyword_sr;
+
+UPDATE suggestions_restaurant
+SET idcat = CASE idcat WHEN 2 THEN 1 WHEN 3 THEN 2 END
+WHERE idcat IN (2, 3);
+
+ALTER TABLE suggestions_restaurant ADD CONSTRAINT uq_keyword_sr UNIQUE (keyword, idrestaurant, idcat);
+
+-- the categories were seeded with explicit ids, so the sequence never moved
+SELECT setval(pg_get_serial_sequence('cat_suggestions', 'idcat'), COALESCE((SELECT MAX(idcat) FROM cat_suggestions), 1));
@@ -0,0 +1,30 @@
+-- Add migration script here
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+DROP TEXT SEARCH CONFIGURATION IF EXISTS public.french_unaccent;
+CREATE TEXT SEARCH CONFIGURATION public.french_unaccent (COPY = pg_catalog.french);
+ALTER TEXT SEARCH CONFIGURATION public.french_unaccent
+    ALTER MAPPING FOR hword, hword_part, word WITH unaccent, french_stem;
+
+ALTER TABLE restaurant ADD COLUMN address TEXT;
+
+-- only the dishes are indexed, not the course labels
+ALTER TABLE meal ADD COLUMN search tsvector GENERATED ALWAYS AS (
+    to_tsvector('public.french_unaccent'::regconfig, jsonb_path_query_array(coalesce(foodies, '[]'::jsonb), '$[*].content[*]'))
+) STORED;
+
+CREATE INDEX idx_meal_search ON meal USING GIN (search);
+
+-- concatenates the dish vectors of a restaurant's meals into its search document
+CREATE AGGREGATE tsvector_agg(tsvector) (SFUNC = tsvector_concat, STYPE = tsvector, INITCOND = '');
+
+DROP TABLE IF EXISTS restaurant_search;
+
+CREATE TABLE restaurant_search(
+    idrestaurant INT PRIMARY KEY,
+    document tsvector NOT NULL,
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT fk_idrestaurant_rs FOREIGN KEY (idrestaurant) REFERENCES restaurant(idrestaurant) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX idx_restaurant_search_document ON restaurant_search USING GIN (document);
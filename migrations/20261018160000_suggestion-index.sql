@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+DROP TABLE IF EXISTS suggestion_index;
+
+-- suggestions aggregated over every restaurant, rebuilt from suggestions_restaurant after each run
+CREATE TABLE suggestion_index(
+    idsuggestionindex serial PRIMARY KEY,
+    term TEXT NOT NULL,
+    folded TEXT NOT NULL,
+    idcat INT NOT NULL,
+    occurrences INT NOT NULL,
+    restaurants INT NOT NULL,
+    CONSTRAINT fk_idcat_si FOREIGN KEY (idcat) REFERENCES cat_suggestions(idcat) ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT uq_suggestion_index UNIQUE (folded, idcat)
+);
+
+CREATE INDEX idx_suggestion_index_folded ON suggestion_index USING GIN (folded gin_trgm_ops);
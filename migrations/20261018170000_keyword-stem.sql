@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE suggestions_restaurant ADD COLUMN stem TEXT;
+-- existing keywords get their stem the next time they are seen
+UPDATE suggestions_restaurant SET stem = folded;
+
+-- rebuilt after the next run
+DELETE FROM suggestion_index;
+ALTER TABLE suggestion_index ADD COLUMN stem TEXT NOT NULL;
+ALTER TABLE suggestion_index DROP CONSTRAINT uq_suggestion_index;
+ALTER TABLE suggestion_index ADD CONSTRAINT uq_suggestion_index UNIQUE (stem, idcat);
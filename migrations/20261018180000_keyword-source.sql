@@ -0,0 +1,17 @@
+-- Add migration script here
+ALTER TABLE suggestions_restaurant RENAME COLUMN last_seen TO last_seen_at;
+
+-- what the keyword was extracted from, existing keywords are mapped from their category
+ALTER TABLE suggestions_restaurant ADD COLUMN source TEXT;
+UPDATE suggestions_restaurant s SET source = CASE lower(trim(c.namecat))
+        WHEN 'restaurant' THEN 'restaurant_name'
+        WHEN 'food' THEN 'dish'
+        ELSE 'meal_title'
+    END
+FROM cat_suggestions c
+WHERE c.idcat = s.idcat;
+UPDATE suggestions_restaurant SET source = 'meal_title' WHERE source IS NULL;
+ALTER TABLE suggestions_restaurant ALTER COLUMN source SET NOT NULL;
+ALTER TABLE suggestions_restaurant ADD CONSTRAINT chk_source_sr CHECK (source IN ('restaurant_name', 'meal_title', 'dish'));
+
+CREATE INDEX idx_suggestions_restaurant_last_seen_at ON suggestions_restaurant(last_seen_at);
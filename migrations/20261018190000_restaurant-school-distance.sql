@@ -0,0 +1,10 @@
+-- Add migration script here
+-- links are computed by the link action, they are rebuilt from scratch on the next run
+DELETE FROM restaurant_school;
+
+ALTER TABLE restaurant_school ALTER COLUMN idrestaurant SET NOT NULL;
+ALTER TABLE restaurant_school ALTER COLUMN idschool SET NOT NULL;
+ALTER TABLE restaurant_school ADD COLUMN distance_km DOUBLE PRECISION NOT NULL;
+ALTER TABLE restaurant_school ADD CONSTRAINT pk_restaurant_school PRIMARY KEY (idschool, idrestaurant);
+
+CREATE INDEX idx_restaurant_school_idrestaurant ON restaurant_school(idrestaurant);
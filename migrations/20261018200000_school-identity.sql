@@ -0,0 +1,19 @@
+-- Add migration script here
+ALTER TABLE school ADD COLUMN code_uai TEXT;
+ALTER TABLE school ADD COLUMN address TEXT;
+ALTER TABLE school ADD COLUMN postcode TEXT;
+ALTER TABLE school ADD COLUMN commune TEXT;
+ALTER TABLE school ADD COLUMN phone TEXT;
+ALTER TABLE school ADD COLUMN school_type TEXT;
+ALTER TABLE school ADD COLUMN website TEXT;
+-- schools missing from the dataset are deactivated, never deleted, since users reference them
+ALTER TABLE school ADD COLUMN active BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE school ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE school ADD COLUMN deactivated_at TIMESTAMPTZ;
+
+-- schools inserted before this migration have no UAI yet, they get one on the next sync
+ALTER TABLE school ADD CONSTRAINT uq_school_code_uai UNIQUE (code_uai);
+
+-- deleting a school used to delete every user attached to it
+ALTER TABLE users DROP CONSTRAINT fk_idschool_user;
+ALTER TABLE users ADD CONSTRAINT fk_idschool_user FOREIGN KEY (idschool) REFERENCES school(idschool) ON DELETE SET NULL ON UPDATE CASCADE;
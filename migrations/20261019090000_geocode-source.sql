@@ -0,0 +1,10 @@
+-- Add migration script here
+-- where the coordinates come from (crous_map, onisep, ban_housenumber, ban_street) and how much
+-- they can be trusted, from 0 to 1
+ALTER TABLE restaurant ADD COLUMN geocode_source TEXT;
+ALTER TABLE restaurant ADD COLUMN geocode_confidence REAL;
+UPDATE restaurant SET geocode_source = 'crous_map', geocode_confidence = 1 WHERE gpscoord IS NOT NULL;
+
+ALTER TABLE school ADD COLUMN geocode_source TEXT;
+ALTER TABLE school ADD COLUMN geocode_confidence REAL;
+UPDATE school SET geocode_source = 'onisep', geocode_confidence = 1 WHERE coords IS NOT NULL;
@@ -0,0 +1,26 @@
+-- Add migration script here
+-- campuses group the schools of a site, from conf/campuses.json or by clustering their coordinates.
+-- They are rebuilt by the link action, ids are kept as long as the name doesn't change
+CREATE TABLE campus(
+    idcampus SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    coords POINT NOT NULL,
+    source TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT uq_campus_name UNIQUE (name),
+    CONSTRAINT ck_campus_source CHECK (source IN ('curated', 'cluster'))
+);
+
+ALTER TABLE school ADD COLUMN idcampus INT;
+ALTER TABLE school ADD CONSTRAINT fk_idcampus_school FOREIGN KEY (idcampus) REFERENCES campus(idcampus) ON DELETE SET NULL;
+CREATE INDEX idx_school_idcampus ON school(idcampus);
+
+CREATE TABLE restaurant_campus(
+    idcampus INT NOT NULL,
+    idrestaurant INT NOT NULL,
+    distance_km DOUBLE PRECISION NOT NULL,
+    CONSTRAINT pk_restaurant_campus PRIMARY KEY (idcampus, idrestaurant),
+    CONSTRAINT fk_idcampus_rcam FOREIGN KEY (idcampus) REFERENCES campus(idcampus) ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT fk_idrestaurant_rcam FOREIGN KEY (idrestaurant) REFERENCES restaurant(idrestaurant) ON DELETE CASCADE ON UPDATE CASCADE
+);
+CREATE INDEX idx_restaurant_campus_idrestaurant ON restaurant_campus(idrestaurant);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- where a row comes from: scraped (crous, onisep) or imported from a local file (import).
+-- Scraped syncs only replace or deactivate their own rows
+ALTER TABLE restaurant ADD COLUMN source TEXT NOT NULL DEFAULT 'crous';
+ALTER TABLE restaurant ADD CONSTRAINT ck_restaurant_source CHECK (source IN ('crous', 'import'));
+CREATE INDEX idx_restaurant_source_url ON restaurant(source, url);
+
+ALTER TABLE school ADD COLUMN source TEXT NOT NULL DEFAULT 'onisep';
+ALTER TABLE school ADD CONSTRAINT ck_school_source CHECK (source IN ('onisep', 'import'));
@@ -0,0 +1,21 @@
+-- Add migration script here
+-- one row per command, written once it is over, outside of its transaction so that failed runs
+-- are kept too
+CREATE TABLE crawl_run(
+    idrun BIGSERIAL PRIMARY KEY,
+    action TEXT NOT NULL,
+    region TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL,
+    status TEXT NOT NULL,
+    fetched INT NOT NULL DEFAULT 0,
+    parsed INT NOT NULL DEFAULT 0,
+    inserted INT NOT NULL DEFAULT 0,
+    updated INT NOT NULL DEFAULT 0,
+    skipped INT NOT NULL DEFAULT 0,
+    errors INT NOT NULL DEFAULT 0,
+    message TEXT,
+    CONSTRAINT ck_status_crawl_run CHECK (status IN ('succeeded', 'failed'))
+);
+
+CREATE INDEX idx_crawl_run_action ON crawl_run(action, started_at DESC);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- campuses are kept by a key rather than by their name, which changes when a cluster gains a
+-- school: the name of a curated campus, a UAI of the schools of a cluster
+ALTER TABLE campus ADD COLUMN key TEXT;
+UPDATE campus SET key = name;
+ALTER TABLE campus ALTER COLUMN key SET NOT NULL;
+ALTER TABLE campus DROP CONSTRAINT uq_campus_name;
+ALTER TABLE campus ADD CONSTRAINT uq_campus_key UNIQUE (source, key);
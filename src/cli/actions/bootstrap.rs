@@ -1,49 +1,82 @@
 use std::{process::ExitCode, sync::Arc};
 
 use async_trait::async_trait;
-use sqlx::{Pool, Postgres};
+use sqlx::PgConnection;
 
 use crate::{
     cli::{Action, ExitResult},
-    models::{keywords::KeywordService, meals::MealService, restaurants::RestaurantService},
+    models::{
+        crawl_run::RunCounts, keywords::KeywordRepository, meals::MealRepository,
+        menu_status::MenuStatusRepository, restaurants::RestaurantRepository, search::SearchIndex,
+    },
 };
 
-use super::{meals::MealsAction, restaurants::RestaurantAction, up::UpAction};
+use super::{link::LinkAction, meals::MealsAction, restaurants::RestaurantAction, up::UpAction};
 
-pub struct BootstrapAction {
-    pub meal_action: Arc<MealsAction>,
-    pub restaurant_action: Arc<RestaurantAction>,
+pub struct BootstrapAction<M, R, K, T, I> {
+    pub keyword_service: Arc<K>,
+    pub meal_action: Arc<MealsAction<M, R, K, T, I>>,
+    pub restaurant_action: Arc<RestaurantAction<R, K, I, PgConnection>>,
     pub up_action: Arc<UpAction>,
+    pub link_action: Arc<LinkAction>,
 }
 
-impl BootstrapAction {
+impl<M, R, K, T, I> BootstrapAction<M, R, K, T, I> {
     pub fn new(
-        pool: Arc<Pool<Postgres>>,
-        meal_service: Arc<MealService>,
-        restaurants_service: Arc<RestaurantService>,
-        keyword_service: Arc<KeywordService>,
+        meal_service: Arc<M>,
+        restaurants_service: Arc<R>,
+        keyword_service: Arc<K>,
+        menu_status_service: Arc<T>,
+        search_service: Arc<I>,
+        link_action: Arc<LinkAction>,
     ) -> Self {
         Self {
+            keyword_service: keyword_service.clone(),
             meal_action: Arc::new(MealsAction::new(
                 meal_service,
                 restaurants_service.clone(),
                 keyword_service.clone(),
+                menu_status_service,
+                search_service.clone(),
             )),
             restaurant_action: Arc::new(RestaurantAction::new(
                 restaurants_service,
                 keyword_service,
+                search_service,
+                Some(link_action.clone()),
             )),
-            up_action: Arc::new(UpAction { pool: pool.clone() }),
+            up_action: Arc::new(UpAction {}),
+            link_action,
         }
     }
 }
 
+// up and link only run on Postgres
 #[async_trait]
-impl Action for BootstrapAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
-        self.up_action.execute().await?;
-        self.restaurant_action.execute().await?;
-        self.meal_action.execute().await?;
+impl<M, R, K, T, I> Action for BootstrapAction<M, R, K, T, I>
+where
+    M: MealRepository<PgConnection>,
+    R: RestaurantRepository<PgConnection>,
+    K: KeywordRepository<PgConnection>,
+    T: MenuStatusRepository<PgConnection>,
+    I: SearchIndex<PgConnection>,
+{
+    // the menus are read from the restaurants just scraped, the database may not have them yet
+    async fn fetch(&self, conn: &mut PgConnection, counts: &mut RunCounts) -> Result<(), ExitResult> {
+        self.restaurant_action.fetch(&mut *conn, counts).await?;
+        self.meal_action.fetch_menus(self.restaurant_action.fetched(), counts).await
+    }
+
+    async fn execute(&self, conn: &mut PgConnection, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        self.up_action.execute(&mut *conn, counts).await?;
+        self.keyword_service.check_categories(&mut *conn).await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("keyword categories check failed: {}", e),
+        })?;
+        self.restaurant_action.execute(&mut *conn, counts).await?;
+        self.meal_action.execute(&mut *conn, counts).await?;
+        // links are not crawled items, only the link command counts them
+        self.link_action.execute(&mut *conn, &mut RunCounts::default()).await?;
         Ok(ExitResult {
             exit_code: ExitCode::from(0),
             message: "Environment bootstrapped successfully".to_string(),
@@ -51,6 +84,10 @@ impl Action for BootstrapAction {
     }
 
     fn help(&self) -> &str {
-        "calls every actions up -> restaurants -> meals, so in one action you can bootstrap a new database with all needed data"
+        "calls every actions up -> restaurants -> meals -> link, so in one action you can bootstrap a new database with all needed data"
+    }
+
+    fn recorded(&self) -> bool {
+        true
     }
 }
@@ -0,0 +1,75 @@
+use std::{process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult},
+    models::{crawl_run::RunCounts, geo::Coordinates, meals::MealRepository},
+};
+
+pub struct FindDishAction<M> {
+    pub meal_service: Arc<M>,
+    pub query: String,
+    pub date: chrono::NaiveDate,
+    pub near: Option<Coordinates>,
+}
+
+impl<M> FindDishAction<M> {
+    pub fn new(
+        meal_service: Arc<M>,
+        query: String,
+        date: chrono::NaiveDate,
+        near: Option<Coordinates>,
+    ) -> Self {
+        Self {
+            meal_service,
+            query,
+            date,
+            near,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send, M: MealRepository<C>> Action<C> for FindDishAction<M> {
+    async fn execute(&self, conn: &mut C, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let matches = self
+            .meal_service
+            .find_dish(&mut *conn, &self.query, self.date, self.near)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("find dish failed: {}", e),
+            })?;
+
+        for found in matches.iter() {
+            let restaurant = match found.distance_km {
+                Some(distance) => format!("{} at {:.1} km", found.name, distance),
+                None => found.name.clone(),
+            };
+            info!(
+                "[{}] {} - {}: {} ({})",
+                found.idrestaurant,
+                restaurant,
+                found.service.as_str(),
+                found.dishes.join(", "),
+                found.url
+            );
+        }
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: format!(
+                "{} services serve \"{}\" on {}",
+                matches.len(),
+                self.query,
+                self.date
+            ),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "find the restaurants serving a dish on a given day"
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashSet, path::PathBuf, process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use tracing::{info, warn};
+
+use crate::{
+    cli::{Action, ExitResult},
+    geocoding::{GeocodeSource, Geocoded, Geocoder},
+    import::{self, ImportError, Imported},
+    models::{
+        crawl_run::RunCounts,
+        keywords::{KeywordBatch, KeywordRepository},
+        restaurants::{RestaurantRepository, SOURCE_CROUS},
+        schools::SchoolRepository,
+        search::SearchIndex,
+    },
+};
+
+pub struct ImportSchoolsAction<S, C: Send> {
+    pub school_service: Arc<S>,
+    // links the imported schools to restaurants
+    pub link_action: Arc<dyn Action<C>>,
+    pub file: PathBuf,
+}
+
+impl<S, C: Send> ImportSchoolsAction<S, C> {
+    pub fn new(school_service: Arc<S>, link_action: Arc<dyn Action<C>>, file: PathBuf) -> Self {
+        Self {
+            school_service,
+            link_action,
+            file,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send, S: SchoolRepository<C>> Action<C> for ImportSchoolsAction<S, C> {
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let schools = import::read(&self.file)
+            .and_then(|records| import::schools(&records))
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: e.to_string(),
+            })?;
+        let geocoder = load_geocoder()?;
+        counts.parsed += schools.len() as i32;
+
+        let schools = schools
+            .into_iter()
+            .map(|imported| {
+                let geocoded = locate(
+                    &imported,
+                    &imported.item.name,
+                    imported.item.address.as_deref(),
+                    imported.item.postcode.as_deref(),
+                    geocoder.as_ref(),
+                );
+                let mut school = imported.item;
+                school.coords = geocoded.map(|geocoded| geocoded.coordinates.to_point());
+                school.geocode_source = geocoded.map(|geocoded| geocoded.source.as_str().to_string());
+                school.geocode_confidence = geocoded.map(|geocoded| geocoded.confidence);
+                school
+            })
+            .collect();
+        let report = self.school_service.import(&mut *conn, schools).await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("school import failed: {}", e),
+        })?;
+        info!(
+            "{}: {} schools inserted, {} updated",
+            self.file.display(),
+            report.inserted,
+            report.updated
+        );
+        counts.inserted += report.inserted as i32;
+        counts.updated += report.updated as i32;
+
+        self.link_action.execute(&mut *conn, &mut RunCounts::default()).await?;
+
+        Ok(ExitResult {
+            exit_code: ExitCode::SUCCESS,
+            message: "schools imported".to_string(),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "import schools from a CSV or GeoJSON file"
+    }
+
+    fn recorded(&self) -> bool {
+        true
+    }
+}
+
+pub struct ImportRestaurantsAction<R, K, I, C: Send> {
+    pub restaurant_service: Arc<R>,
+    pub keyword_service: Arc<K>,
+    pub search_service: Arc<I>,
+    // links the imported restaurants to schools and campuses
+    pub link_action: Arc<dyn Action<C>>,
+    pub file: PathBuf,
+}
+
+impl<R, K, I, C: Send> ImportRestaurantsAction<R, K, I, C> {
+    pub fn new(
+        restaurant_service: Arc<R>,
+        keyword_service: Arc<K>,
+        search_service: Arc<I>,
+        link_action: Arc<dyn Action<C>>,
+        file: PathBuf,
+    ) -> Self {
+        Self {
+            restaurant_service,
+            keyword_service,
+            search_service,
+            link_action,
+            file,
+        }
+    }
+}
+
+#[async_trait]
+impl<C, R, K, I> Action<C> for ImportRestaurantsAction<R, K, I, C>
+where
+    C: Send,
+    R: RestaurantRepository<C>,
+    K: KeywordRepository<C>,
+    I: SearchIndex<C>,
+{
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let restaurants = import::read(&self.file)
+            .and_then(|records| import::restaurants(&records))
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: e.to_string(),
+            })?;
+        // the import upserts on imported urls only, a scraped one would be duplicated
+        let scraped = self
+            .restaurant_service
+            .find_all(&mut *conn)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("restaurant import failed: {}", e),
+            })?
+            .into_iter()
+            .filter(|restaurant| restaurant.source == SOURCE_CROUS)
+            .map(|restaurant| restaurant.url)
+            .collect::<HashSet<_>>();
+        let errors = restaurants
+            .iter()
+            .filter(|imported| scraped.contains(&imported.item.url))
+            .map(|imported| format!("line {}: url {} is a scraped CROUS restaurant", imported.line, imported.item.url))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(ExitResult {
+                exit_code: ExitCode::from(2),
+                message: ImportError::Invalid(errors).to_string(),
+            });
+        }
+        let geocoder = load_geocoder()?;
+        counts.parsed += restaurants.len() as i32;
+
+        let restaurants = restaurants
+            .into_iter()
+            .map(|imported| {
+                let geocoded = locate(
+                    &imported,
+                    &imported.item.name,
+                    imported.item.address.as_deref(),
+                    None,
+                    geocoder.as_ref(),
+                );
+                let mut restaurant = imported.item;
+                restaurant.gpscoord = geocoded.map(|geocoded| geocoded.coordinates.to_point());
+                restaurant.geocode_source = geocoded.map(|geocoded| geocoded.source.as_str().to_string());
+                restaurant.geocode_confidence = geocoded.map(|geocoded| geocoded.confidence);
+                restaurant
+            })
+            .collect();
+        let imported = self
+            .restaurant_service
+            .import(&mut *conn, restaurants)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("restaurant import failed: {}", e),
+            })?;
+        let mut keywords = KeywordBatch::default();
+        for (restaurant, _) in imported.iter() {
+            self.keyword_service.extractor().collect_restaurant(&mut keywords, restaurant);
+        }
+        self.keyword_service
+            .create_many(&mut *conn, keywords)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("keyword insertion failed: {}", err),
+            })?;
+        let inserted = imported.iter().filter(|(_, inserted)| *inserted).count();
+        info!(
+            "{}: {} restaurants inserted, {} updated",
+            self.file.display(),
+            inserted,
+            imported.len() - inserted
+        );
+        counts.inserted += inserted as i32;
+        counts.updated += (imported.len() - inserted) as i32;
+
+        self.search_service.refresh(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("search index refresh failed: {}", err),
+        })?;
+        self.keyword_service.refresh_suggestions(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("suggestion index refresh failed: {}", err),
+        })?;
+        self.link_action.execute(&mut *conn, &mut RunCounts::default()).await?;
+
+        Ok(ExitResult {
+            exit_code: ExitCode::SUCCESS,
+            message: "restaurants imported".to_string(),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "import restaurants from a CSV or GeoJSON file"
+    }
+
+    fn recorded(&self) -> bool {
+        true
+    }
+}
+
+fn load_geocoder() -> Result<Option<Geocoder>, ExitResult> {
+    Geocoder::load().map_err(|e| ExitResult {
+        exit_code: ExitCode::from(2),
+        message: e.to_string(),
+    })
+}
+
+// coordinates of the file when given, the BAN otherwise
+fn locate<T>(
+    imported: &Imported<T>,
+    name: &str,
+    address: Option<&str>,
+    postcode: Option<&str>,
+    geocoder: Option<&Geocoder>,
+) -> Option<Geocoded> {
+    if let Some(coordinates) = imported.coordinates {
+        return Some(Geocoded {
+            coordinates,
+            source: GeocodeSource::Import,
+            confidence: 1.0,
+        });
+    }
+    let geocoded = match (geocoder, address) {
+        (Some(geocoder), Some(address)) => geocoder.geocode(address, postcode),
+        _ => None,
+    };
+    match geocoded {
+        Some(geocoded) => info!(
+            "line {} {}: geocoded from {} ({})",
+            imported.line,
+            name,
+            geocoded.source.as_str(),
+            geocoded.confidence
+        ),
+        None => warn!("line {} {}: no coordinates", imported.line, name),
+    }
+    geocoded
+}
@@ -0,0 +1,177 @@
+use std::{process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult},
+    models::{
+        crawl_run::RunCounts, keywords::KeywordRepository, meals::MealRepository,
+        restaurants::RestaurantRepository,
+    },
+};
+
+pub struct KeywordsGcAction<K, R, M> {
+    pub keyword_service: Arc<K>,
+    pub restaurant_service: Arc<R>,
+    pub meal_service: Arc<M>,
+    pub older_than: chrono::Duration,
+}
+
+impl<K, R, M> KeywordsGcAction<K, R, M> {
+    pub fn new(
+        keyword_service: Arc<K>,
+        restaurant_service: Arc<R>,
+        meal_service: Arc<M>,
+        older_than: chrono::Duration,
+    ) -> Self {
+        Self {
+            keyword_service,
+            restaurant_service,
+            meal_service,
+            older_than,
+        }
+    }
+}
+
+#[async_trait]
+impl<C, K, R, M> Action<C> for KeywordsGcAction<K, R, M>
+where
+    C: Send,
+    K: KeywordRepository<C>,
+    R: RestaurantRepository<C>,
+    M: MealRepository<C>,
+{
+    async fn execute(&self, conn: &mut C, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let restaurants = self
+            .restaurant_service
+            .find_all(&mut *conn)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("can't get restaurants: {}", e),
+            })?;
+        let meals = self.meal_service.find_all(&mut *conn).await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("can't get meals: {}", e),
+        })?;
+
+        let supported = self.keyword_service.extractor().supported(&restaurants, &meals);
+        info!(
+            "{} keywords supported by {} restaurants and {} meals",
+            supported.len(),
+            restaurants.len(),
+            meals.len()
+        );
+
+        let deleted = self
+            .keyword_service
+            .gc(&mut *conn, self.older_than, &supported)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("keywords gc failed: {}", e),
+            })?;
+        self.keyword_service.refresh_suggestions(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("suggestion index refresh failed: {}", err),
+        })?;
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: format!("{} stale keywords deleted", deleted),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "delete the keywords no restaurant or meal supports anymore"
+    }
+
+    fn recorded(&self) -> bool {
+        true
+    }
+}
+
+// parses an age such as "30d", "12h" or "2w"
+pub fn parse_age(age: &str) -> Result<chrono::Duration, String> {
+    let age = age.trim();
+    let unit = age.chars().last().ok_or("empty age")?;
+    let value: i64 = age[..age.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| format!("invalid age {:?}, expected a number followed by h, d or w", age))?;
+    match unit {
+        'h' => Ok(chrono::Duration::hours(value)),
+        'd' => Ok(chrono::Duration::days(value)),
+        'w' => Ok(chrono::Duration::weeks(value)),
+        _ => Err(format!("invalid age unit {:?}, expected h, d or w", unit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::actions::suggest::SuggestAction,
+        models::{
+            keywords::{KeywordBatch, KeywordExtractor, Source},
+            memory::{MemoryKeywords, MemoryMeals, MemoryRestaurants},
+            restaurants::{Restaurant, SOURCE_CROUS},
+        },
+        text::synonyms::Synonyms,
+    };
+
+    #[tokio::test]
+    async fn test_gc_without_database() {
+        let restaurants = Arc::new(MemoryRestaurants::new());
+        let synonyms = Arc::new(Synonyms::load().unwrap());
+        let meals = Arc::new(MemoryMeals::new(restaurants.clone(), synonyms.clone()));
+        let extractor = KeywordExtractor::new(synonyms);
+        let keywords = Arc::new(MemoryKeywords::new(extractor.clone()));
+
+        let restaurant = restaurants
+            .create(
+                &mut (),
+                Restaurant {
+                    idrestaurant: None,
+                    url: "https://www.crous-montpellier.fr/restaurant/resto-u-richter/".to_string(),
+                    name: "Resto U' Richter".to_string(),
+                    gpscoord: None,
+                    hours: None,
+                    address: None,
+                    geocode_source: None,
+                    geocode_confidence: None,
+                    source: SOURCE_CROUS.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let mut batch = KeywordBatch::default();
+        extractor.collect_restaurant(&mut batch, &restaurant);
+        // no meal serves it anymore
+        extractor.collect(&mut batch, "Frites", 1, Source::Dish);
+        keywords.create_many(&mut (), batch).await.unwrap();
+
+        let gc = KeywordsGcAction::new(
+            keywords.clone(),
+            restaurants,
+            meals,
+            chrono::Duration::zero(),
+        );
+        let result = gc.execute(&mut (), &mut RunCounts::default()).await.ok().unwrap();
+        assert_eq!(result.message, "1 stale keywords deleted");
+
+        let suggest = SuggestAction::new(keywords, "richt".to_string(), 10);
+        let result = suggest.execute(&mut (), &mut RunCounts::default()).await.ok().unwrap();
+        assert_eq!(result.message, "1 suggestions for \"richt\"");
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(chrono::Duration::days(30)));
+        assert_eq!(parse_age("12h"), Ok(chrono::Duration::hours(12)));
+        assert_eq!(parse_age("2w"), Ok(chrono::Duration::weeks(2)));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::HashSet, process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use sqlx::PgConnection;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult},
+    models::{campus::CampusService, crawl_run::RunCounts, restaurant_school::RestaurantSchoolService},
+};
+
+pub struct LinkAction {
+    pub restaurant_school_service: Arc<RestaurantSchoolService>,
+    pub campus_service: Arc<CampusService>,
+}
+
+impl LinkAction {
+    pub fn new(
+        restaurant_school_service: Arc<RestaurantSchoolService>,
+        campus_service: Arc<CampusService>,
+    ) -> Self {
+        Self {
+            restaurant_school_service,
+            campus_service,
+        }
+    }
+}
+
+#[async_trait]
+impl Action for LinkAction {
+    async fn execute(&self, conn: &mut PgConnection, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let settings = self.restaurant_school_service.settings;
+        let links = self
+            .restaurant_school_service
+            .rebuild(&mut *conn)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("restaurant school links failed: {}", e),
+            })?;
+        let schools: HashSet<i32> = links.iter().map(|link| link.idschool).collect();
+        info!(
+            "{} schools linked to restaurants within {} km, or to their {} nearest ones",
+            schools.len(),
+            settings.radius_km,
+            settings.nearest
+        );
+
+        // Group the schools into campuses, linked to restaurants the same way
+        let campus_links = self.campus_service.rebuild(&mut *conn).await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("campus links failed: {}", e),
+        })?;
+        for (idcampus, campus) in campus_links.campuses.iter() {
+            info!(
+                "campus {} ({}): {} schools, {} restaurants",
+                campus.name,
+                campus.source.as_str(),
+                campus.schools.len(),
+                campus_links
+                    .links
+                    .iter()
+                    .filter(|link| link.idcampus == *idcampus)
+                    .count()
+            );
+        }
+
+        counts.inserted += (links.len() + campus_links.links.len()) as i32;
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: format!(
+                "{} restaurant school links, {} campuses",
+                links.len(),
+                campus_links.campuses.len()
+            ),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "link every school and campus to its nearby restaurants"
+    }
+
+    fn recorded(&self) -> bool {
+        true
+    }
+}
@@ -1,24 +1,43 @@
-use std::{process::ExitCode, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use chrono::TimeZone;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
     cli::{Action, ExitResult},
+    dietary::Classifier,
     models::{
-        keywords::{Category, KeywordService},
-        meals::{Meal, MealService},
-        restaurants::{Restaurant, RestaurantService},
+        crawl_run::RunCounts,
+        keywords::{KeywordBatch, KeywordRepository, Source},
+        meals::{Course, Meal, MealRepository, Service},
+        menu_status::{MenuScrapeStatus, MenuStatusRepository, ScrapeOutcome},
+        restaurants::{Restaurant, RestaurantRepository, SOURCE_CROUS},
+        search::SearchIndex,
     },
 };
 
-pub struct MealsAction {
-    pub meal_service: Arc<MealService>,
-    pub restaurants_service: Arc<RestaurantService>,
-    pub keyword_service: Arc<KeywordService>,
+pub struct MealsAction<M, R, K, T, I> {
+    pub meal_service: Arc<M>,
+    pub restaurants_service: Arc<R>,
+    pub keyword_service: Arc<K>,
+    pub menu_status_service: Arc<T>,
+    pub search_service: Arc<I>,
+    // the menus read by fetch, written by execute
+    fetched: Mutex<Option<Vec<Menu>>>,
+}
+
+// the meals read from the page of a restaurant, or why there are none
+pub struct Menu {
+    pub restaurant: Restaurant,
+    pub meals: Result<Vec<Meal>, MealError>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -27,86 +46,94 @@ pub struct MealHTML {
     pub foodies: Vec<Foody>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "StoredFoody")]
 pub struct Foody {
     #[serde(rename = "type")]
     pub r#type: String,
+    pub course: Course,
     pub content: Vec<String>,
 }
 
-impl MealsAction {
+// foody as stored in meal.foodies, rows written before the course field have none and get it
+// from their label
+#[derive(Deserialize)]
+struct StoredFoody {
+    #[serde(rename = "type")]
+    r#type: String,
+    #[serde(default)]
+    course: Option<Course>,
+    content: Vec<String>,
+}
+
+impl From<StoredFoody> for Foody {
+    fn from(stored: StoredFoody) -> Self {
+        Self {
+            course: stored
+                .course
+                .unwrap_or_else(|| Course::from_label(&stored.r#type)),
+            r#type: stored.r#type,
+            content: stored.content,
+        }
+    }
+}
+
+impl<M, R, K, T, I> MealsAction<M, R, K, T, I> {
     pub fn new(
-        meal_service: Arc<MealService>,
-        restaurants_service: Arc<RestaurantService>,
-        keyword_service: Arc<KeywordService>,
+        meal_service: Arc<M>,
+        restaurants_service: Arc<R>,
+        keyword_service: Arc<K>,
+        menu_status_service: Arc<T>,
+        search_service: Arc<I>,
     ) -> Self {
         Self {
             meal_service,
             restaurants_service,
             keyword_service,
+            menu_status_service,
+            search_service,
+            fetched: Mutex::new(None),
         }
     }
 }
 
+// runs on any storage the repositories support, Postgres, SQLite or memory
 #[async_trait]
-impl Action for MealsAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
-        let restaurants = self
-            .restaurants_service
-            .find_all()
-            .await
-            .map(|restaurants| {
-                let mut restaurants_url: Vec<Restaurant> = Vec::new();
-                for restaurant in restaurants.iter() {
-                    if restaurant.url.is_empty() || restaurant.idrestaurant.is_none() {
-                        continue;
-                    }
-                    restaurants_url.push(restaurant.clone());
-                }
-                restaurants_url
-            })
-            .map_err(|e| {
-                ExitResult {
-                    exit_code: ExitCode::from(2),
-                    message: format!("can't find restaurants: {}", e),
-                }
-            });
+impl<C, M, R, K, T, I> Action<C> for MealsAction<M, R, K, T, I>
+where
+    C: Send,
+    M: MealRepository<C>,
+    R: RestaurantRepository<C>,
+    K: KeywordRepository<C>,
+    T: MenuStatusRepository<C>,
+    I: SearchIndex<C>,
+{
+    async fn fetch(&self, conn: &mut C, counts: &mut RunCounts) -> Result<(), ExitResult> {
+        let restaurants = self.restaurants(&mut *conn).await?;
+        self.fetch_menus(restaurants, counts).await
+    }
 
-        let tasks: Vec<_> = match restaurants {
-            Ok(restaurants) => restaurants
-                .into_iter()
-                .map(|restaurant| {
-                    tokio::spawn(async move {
-                        match scrape_meals(restaurant.clone()).await {
-                            Ok(meals) => {
-                                info!("[{}] menu found", restaurant.name);
-                                meals
-                            },
-                            Err(err) => {
-                                match err {
-                                    MealError::DomIssue(element) => {
-                                        error!("[{}] couldn't find element in DOM : {}",restaurant.name,  element);
-                                    }
-                                    MealError::NoMenuFound => {
-                                        error!("[{}] no menu found", restaurant.name);
-                                    }
-                                    MealError::NoDateFound => {
-                                        error!("[{}] no date found", restaurant.name);
-                                    }
-                                    MealError::Reqwest(message) => {
-                                        error!("[{}] {}",restaurant.name, message);
-                                    }
-                                };
-                                Vec::new()
-                            },
-                        }
-                    })
-                })
-                .collect(),
-            Err(exit_result) => return Err(exit_result),
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let fetched = self.fetched.lock().unwrap().take();
+        let menus = match fetched {
+            Some(menus) => menus,
+            None => {
+                let restaurants = self.restaurants(&mut *conn).await?;
+                self.scrape(restaurants, counts).await?
+            }
         };
+        let run_started_at = chrono::Utc::now();
+
+        // menus may have been read before their restaurants were written, as in bootstrap, ids
+        // are only known now
+        let ids: HashMap<String, i32> = self
+            .restaurants(&mut *conn)
+            .await?
+            .into_iter()
+            .filter_map(|restaurant| restaurant.idrestaurant.map(|id| (restaurant.url, id)))
+            .collect();
 
-        match self.meal_service.clean().await {
+        match self.meal_service.clean(&mut *conn).await {
             Ok(_) => (),
             Err(err) => {
                 return Err(ExitResult {
@@ -116,43 +143,96 @@ impl Action for MealsAction {
             }
         }
 
-        for task in tasks {
-            match task.await {
+        let mut keywords = KeywordBatch::default();
+        for Menu { restaurant, meals } in menus {
+            let Some(&idrestaurant) = ids.get(&restaurant.url) else {
+                warn!("[{}] restaurant removed since its menu was read", restaurant.name);
+                counts.skipped += 1;
+                continue;
+            };
+            match meals {
+                Err(err) => {
+                    match err.outcome() {
+                        ScrapeOutcome::NotPublished | ScrapeOutcome::Closed => counts.skipped += 1,
+                        _ => counts.errors += 1,
+                    }
+                    self.create_status(&mut *conn, MenuScrapeStatus {
+                        idrestaurant: i64::from(idrestaurant),
+                        day: run_started_at.date_naive(),
+                        run_started_at,
+                        outcome: err.outcome(),
+                        detail: Some(err.to_string()),
+                        meal_count: 0,
+                    })
+                    .await?;
+                }
                 Ok(meals) => {
-                    for meal in meals {
-                        match self.meal_service.create(&meal).await {
-                            Ok(_) => {
-                                for foodies in meal.foodies.iter() {
-                                    for content in foodies.content.iter() {
-                                        self.keyword_service
-                                            .create(
-                                                content.clone(),
-                                                meal.idrestaurant,
-                                                Category::Food,
-                                            )
-                                            .await
-                                            .map_err(|e| {
-                                                ExitResult {
-                                                    exit_code: ExitCode::from(2),
-                                                    message: format!("can't create keyword: {}", e),
-                                                }
-                                            })?;
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                error!("error: {}", e);
-                            }
+                    counts.parsed += 1;
+                    let meals: Vec<Meal> = meals
+                        .into_iter()
+                        .map(|meal| Meal {
+                            idrestaurant: i64::from(idrestaurant),
+                            ..meal
+                        })
+                        .collect();
+                    let day = meals
+                        .first()
+                        .map(|meal| meal.day.date_naive())
+                        .unwrap_or(run_started_at.date_naive());
+                    let total = meals.len();
+                    let meals = self.insert_meals(&mut *conn, &restaurant, meals).await;
+                    for meal in meals.iter() {
+                        self.keyword_service.extractor().collect(
+                            &mut keywords,
+                            &meal.typemeal,
+                            meal.idrestaurant,
+                            Source::MealTitle,
+                        );
+                        for content in meal.foodies.iter().flat_map(|foody| foody.content.iter()) {
+                            self.keyword_service.extractor().collect(
+                                &mut keywords,
+                                content,
+                                meal.idrestaurant,
+                                Source::Dish,
+                            );
                         }
                     }
-                }
-                Err(_) => {
-                    error!("error");
+                    let failed = total - meals.len();
+                    counts.inserted += meals.len() as i32;
+                    counts.errors += failed as i32;
+                    let meal_count = meals.len() as i32;
+                    let detail = (failed > 0).then(|| format!("{} of {} meal insertions failed", failed, total));
+                    self.create_status(&mut *conn, MenuScrapeStatus {
+                        idrestaurant: i64::from(idrestaurant),
+                        day,
+                        run_started_at,
+                        outcome: ScrapeOutcome::Ok,
+                        detail,
+                        meal_count,
+                    })
+                    .await?;
                 }
             }
         }
 
-        // Collect the results and handle any errors
+        let keyword_count = keywords.len();
+        self.keyword_service
+            .create_many(&mut *conn, keywords)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("can't create keyword: {}", e),
+            })?;
+        info!("{} keywords written", keyword_count);
+
+        self.search_service.refresh(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("search index refresh failed: {}", err),
+        })?;
+        self.keyword_service.refresh_suggestions(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("suggestion index refresh failed: {}", err),
+        })?;
 
         Ok(ExitResult {
             exit_code: ExitCode::from(0),
@@ -163,18 +243,207 @@ impl Action for MealsAction {
     fn help(&self) -> &str {
         "scrape meals on all restaurants available in the given database"
     }
+
+    fn recorded(&self) -> bool {
+        true
+    }
+}
+
+impl<M, R, K, T, I> MealsAction<M, R, K, T, I> {
+    // reads the menus of the restaurants, kept for execute
+    pub async fn fetch_menus(&self, restaurants: Vec<Restaurant>, counts: &mut RunCounts) -> Result<(), ExitResult> {
+        let menus = self.scrape(restaurants, counts).await?;
+        *self.fetched.lock().unwrap() = Some(menus);
+        Ok(())
+    }
+
+    async fn restaurants<C: Send>(&self, conn: &mut C) -> Result<Vec<Restaurant>, ExitResult>
+    where
+        R: RestaurantRepository<C>,
+    {
+        self.restaurants_service
+            .find_all(&mut *conn)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("can't find restaurants: {}", e),
+            })
+    }
+
+    async fn scrape(&self, restaurants: Vec<Restaurant>, counts: &mut RunCounts) -> Result<Vec<Menu>, ExitResult> {
+        let classifier = Arc::new(Classifier::load().map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: e.to_string(),
+        })?);
+        info!("dietary dictionary v{} loaded", classifier.version());
+
+        // imported restaurants have no CROUS page to read menus from
+        let restaurants: Vec<Restaurant> = restaurants
+            .into_iter()
+            .filter(|restaurant| !restaurant.url.is_empty() && restaurant.source == SOURCE_CROUS)
+            .collect();
+
+        // each task is kept with its restaurant, so that a task that panicked still gets its status
+        let tasks: Vec<_> = restaurants
+            .into_iter()
+            .map(|restaurant| {
+                let classifier = classifier.clone();
+                let scraped = restaurant.clone();
+                (scraped, tokio::spawn(async move {
+                    let result = scrape_meals(restaurant.clone(), classifier).await;
+                    match &result {
+                        Ok(_) => {
+                            info!("[{}] menu found", restaurant.name);
+                        },
+                        Err(err) => {
+                            match err {
+                                MealError::DomIssue(element) => {
+                                    error!("[{}] couldn't find element in DOM : {}",restaurant.name,  element);
+                                }
+                                MealError::NotPublished(detail) => {
+                                    info!("[{}] no menu published: {}", restaurant.name, detail);
+                                }
+                                MealError::Closed(detail) => {
+                                    info!("[{}] restaurant closed: {}", restaurant.name, detail);
+                                }
+                                MealError::NoDateFound => {
+                                    error!("[{}] no date found", restaurant.name);
+                                }
+                                MealError::Reqwest(message) => {
+                                    error!("[{}] {}",restaurant.name, message);
+                                }
+                                MealError::Task(_) => (),
+                            };
+                        },
+                    }
+                    result
+                }))
+            })
+            .collect();
+        counts.fetched += tasks.len() as i32;
+
+        let mut menus = Vec::new();
+        for (restaurant, task) in tasks {
+            let meals = match task.await {
+                Ok(meals) => meals,
+                Err(err) => {
+                    error!("[{}] scrape task failed: {}", restaurant.name, err);
+                    Err(MealError::Task(err.to_string()))
+                }
+            };
+            menus.push(Menu { restaurant, meals });
+        }
+        Ok(menus)
+    }
+
+    // the meals of a restaurant are written together. When that fails they are retried one by
+    // one, so that a bad meal only loses itself, returns the ones written
+    async fn insert_meals<C: Send>(&self, conn: &mut C, restaurant: &Restaurant, meals: Vec<Meal>) -> Vec<Meal>
+    where
+        M: MealRepository<C>,
+    {
+        let err = match self.meal_service.create_many(&mut *conn, &meals).await {
+            Ok(_) => return meals,
+            Err(err) => err,
+        };
+        warn!("[{}] meal insertion failed, retried meal by meal: {}", restaurant.name, err);
+        let mut inserted = Vec::new();
+        for meal in meals {
+            match self.meal_service.create_many(&mut *conn, std::slice::from_ref(&meal)).await {
+                Ok(_) => inserted.push(meal),
+                Err(err) => error!("[{}] meal {} not inserted: {}", restaurant.name, meal.typemeal, err),
+            }
+        }
+        inserted
+    }
+
+    async fn create_status<C: Send>(
+        &self,
+        conn: &mut C,
+        status: MenuScrapeStatus,
+    ) -> Result<(), ExitResult>
+    where
+        T: MenuStatusRepository<C>,
+    {
+        self.menu_status_service
+            .create(&mut *conn, &status)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("can't create menu scrape status: {}", e),
+            })
+    }
 }
 
 pub enum MealError {
-    NoMenuFound,
+    NotPublished(String),
+    Closed(String),
     NoDateFound,
     DomIssue(String),
-    Reqwest(String)
+    Reqwest(String),
+    // the scrape task panicked or was cancelled
+    Task(String),
+}
+
+impl MealError {
+    pub fn outcome(&self) -> ScrapeOutcome {
+        match self {
+            MealError::NotPublished(_) => ScrapeOutcome::NotPublished,
+            MealError::Closed(_) => ScrapeOutcome::Closed,
+            MealError::NoDateFound | MealError::DomIssue(_) | MealError::Task(_) => ScrapeOutcome::ParseError,
+            MealError::Reqwest(_) => ScrapeOutcome::FetchError,
+        }
+    }
+}
+
+impl Display for MealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MealError::NotPublished(detail) => write!(f, "no menu published: {}", detail),
+            MealError::Closed(detail) => write!(f, "closed: {}", detail),
+            MealError::NoDateFound => write!(f, "no date found"),
+            MealError::DomIssue(element) => write!(f, "couldn't find element in DOM: {}", element),
+            MealError::Reqwest(message) => write!(f, "{}", message),
+            MealError::Task(message) => write!(f, "scrape task failed: {}", message),
+        }
+    }
+}
+
+// CROUS pages without a menu usually say why, we look for these sentences to tell a closed
+// restaurant or an unpublished menu apart from a change in the DOM
+fn unavailable_menu(text: &str) -> Option<MealError> {
+    let text = text.to_lowercase();
+    let closed = [
+        "restaurant fermé",
+        "actuellement fermé",
+        "fermé jusqu",
+        "fermé pour",
+        "fermeture exceptionnelle",
+        "fermeture estivale",
+        "fermeture annuelle",
+    ];
+    let not_published = [
+        "menu non communiqué",
+        "non communiqué",
+        "pas de menu",
+        "aucun menu",
+        "menu à venir",
+        "menu indisponible",
+    ];
+    if let Some(sentence) = not_published.iter().find(|sentence| text.contains(*sentence)) {
+        return Some(MealError::NotPublished(sentence.to_string()));
+    }
+    if let Some(sentence) = closed.iter().find(|sentence| text.contains(*sentence)) {
+        return Some(MealError::Closed(sentence.to_string()));
+    }
+    None
 }
 
-async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
+async fn scrape_meals(
+    restaurant: Restaurant,
+    classifier: Arc<Classifier>,
+) -> Result<Vec<Meal>, MealError> {
     let url = restaurant.url;
-    let id = restaurant.idrestaurant.unwrap();
     let resp = reqwest::get(url)
         .await
         .map_err(|e| MealError::Reqwest(format!("Reqwest error : {}", e)))?
@@ -182,13 +451,16 @@ async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
         .await
         .map_err(|e| MealError::Reqwest(format!("Reqwest to text error : {}", e)))?;
     let document = Html::parse_document(&resp);
-    let menu_selector = Selector::parse(".menu").map_err(|_| MealError::NoMenuFound)?;
+    let menu_selector = Selector::parse(".menu").map_err(|_| MealError::DomIssue(".menu".to_string()))?;
     let menu_element = document.select(&menu_selector);
-    let date_selector = Selector::parse(".menu_date_title").map_err(|_| MealError::NoMenuFound)?;
-    let date_element = menu_element
-        .clone()
-        .next()
-        .ok_or(MealError::NoDateFound)?;
+    let date_selector = Selector::parse(".menu_date_title").map_err(|_| MealError::DomIssue(".menu_date_title".to_string()))?;
+    let date_element = match menu_element.clone().next() {
+        Some(element) => element,
+        None => {
+            let text = document.root_element().text().collect::<String>();
+            return Err(unavailable_menu(&text).unwrap_or(MealError::DomIssue(".menu".to_string())));
+        }
+    };
 
     let date_element = date_element.select(&date_selector);
 
@@ -200,11 +472,11 @@ async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
         .collect::<String>();
 
     let meal_selector = Selector::parse(".meal").map_err(|_| MealError::DomIssue(".meal".to_string()))?;
-    let meal_element = menu_element
+    let menu = menu_element
         .clone()
         .next()
-        .ok_or(MealError::NoMenuFound)?
-        .select(&meal_selector);
+        .ok_or(MealError::DomIssue(".menu".to_string()))?;
+    let meal_element = menu.select(&meal_selector);
 
     let mut meals: Vec<Meal> = Vec::new();
 
@@ -215,7 +487,7 @@ async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
         let meal_title = meal_title_element
             .into_iter()
             .next()
-            .ok_or(MealError::NoMenuFound)?
+            .ok_or(MealError::DomIssue(".meal_title".to_string()))?
             .text()
             .collect::<String>();
 
@@ -225,13 +497,22 @@ async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
 
         let mut meal_foodies: Vec<Foody> = Vec::new();
         for meal_foodie in meal_foodies_element {
-            // get first element of meal foodie inner html after spliting by <ul>
-            let meal_foodie_title = meal_foodie
-                .inner_html()
-                .split("<ul>")
-                .next()
-                .unwrap()
-                .to_string();
+            // get first element of meal foodie inner html after spliting by <ul>, without its markup
+            let meal_foodie_title = Html::parse_fragment(
+                meal_foodie.inner_html().split("<ul>").next().unwrap(),
+            )
+            .root_element()
+            .text()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+            let course = Course::from_label(&meal_foodie_title);
+            if let Course::Other(label) = &course {
+                warn!("[{}] unknown course label: {}", restaurant.name, label);
+            }
 
             let foodie_content_selector = Selector::parse("ul li").map_err(|_| MealError::DomIssue("ul li".to_string()))?;
             let foodie_content_element = meal_foodie.select(&foodie_content_selector);
@@ -245,22 +526,44 @@ async fn scrape_meals(restaurant: Restaurant) -> Result<Vec<Meal>, MealError> {
 
             meal_foodies.push(Foody {
                 r#type: meal_foodie_title,
+                course,
                 content: foodie_content,
             });
         }
         let meal_html = MealHTML {
-            title: meal_title,
+            title: meal_title.trim().to_string(),
             foodies: meal_foodies,
         };
 
+        let service = Service::from_label(&meal_html.title);
+        if let Service::Other(label) = &service {
+            warn!("[{}] unknown meal service label: {}", restaurant.name, label);
+        }
+
         meals.push(Meal {
             day: parse_date(date.clone()),
             typemeal: meal_html.title,
+            service,
+            tags: sqlx::types::Json(classifier.classify(&meal_html.foodies)),
             foodies: sqlx::types::Json(meal_html.foodies),
-            idrestaurant: i64::from(id),
+            // set once the meals are written, the restaurant may not be yet
+            idrestaurant: 0,
         })
     }
 
+    let dishes = meals
+        .iter()
+        .flat_map(|meal| meal.foodies.iter())
+        .flat_map(|foody| foody.content.iter())
+        .filter(|dish| !dish.trim().is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    if dishes.is_empty() || dishes.iter().all(|dish| unavailable_menu(dish).is_some()) {
+        let text = menu.text().collect::<String>();
+        return Err(unavailable_menu(&text)
+            .unwrap_or(MealError::NotPublished("no dish listed".to_string())));
+    }
+
     Ok(meals)
 }
 
@@ -303,3 +606,125 @@ fn parse_date(date: String) -> chrono::DateTime<chrono::Utc> {
         .with_ymd_and_hms(year as i32, month, day, 0, 0, 0)
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{
+        models::{
+            keywords::KeywordExtractor,
+            memory::{MemoryKeywords, MemoryMeals, MemoryMenuStatuses, MemoryRestaurants, MemorySearch},
+            restaurants::SOURCE_IMPORT,
+        },
+        text::synonyms::Synonyms,
+    };
+
+    // a restaurant page answering every request with the same menu
+    async fn page(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/", address)
+    }
+
+    fn restaurant(url: &str, name: &str, source: &str) -> Restaurant {
+        Restaurant {
+            idrestaurant: None,
+            url: url.to_string(),
+            name: name.to_string(),
+            gpscoord: None,
+            hours: None,
+            address: None,
+            geocode_source: None,
+            geocode_confidence: None,
+            source: source.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_meals_in_memory() {
+        let url = page(
+            r#"<html><body><div class="menu">
+                <time class="menu_date_title">Menu du lundi 19 octobre 2026</time>
+                <div class="meal">
+                    <div class="meal_title">Déjeuner</div>
+                    <ul class="meal_foodies"><li>Plats<ul><li>Lasagnes</li><li>Frites</li></ul></li></ul>
+                </div>
+            </div></body></html>"#,
+        )
+        .await;
+        let synonyms = Arc::new(Synonyms::load().unwrap());
+        let restaurants = Arc::new(MemoryRestaurants::new());
+        let meals = Arc::new(MemoryMeals::new(restaurants.clone(), synonyms.clone()));
+        let keywords = Arc::new(MemoryKeywords::new(KeywordExtractor::new(synonyms)));
+        let statuses = Arc::new(MemoryMenuStatuses::new());
+        let scraped = restaurants
+            .create(&mut (), restaurant(&url, "Resto U Triolet", SOURCE_CROUS))
+            .await
+            .unwrap();
+        restaurants
+            .create(&mut (), restaurant("http://127.0.0.1:1/", "Cafet école", SOURCE_IMPORT))
+            .await
+            .unwrap();
+
+        let action = MealsAction::new(
+            meals.clone(),
+            restaurants,
+            keywords.clone(),
+            statuses.clone(),
+            Arc::new(MemorySearch::default()),
+        );
+        let mut counts = RunCounts::default();
+        action.fetch(&mut (), &mut counts).await.unwrap_or_else(|_| panic!("fetch failed"));
+        let Ok(result) = action.execute(&mut (), &mut counts).await else {
+            panic!("meals failed");
+        };
+        assert_eq!(result.message, "meals done");
+
+        // only the CROUS restaurant is scraped
+        assert_eq!((counts.fetched, counts.parsed, counts.inserted), (1, 1, 1));
+        let stored = meals.find_all(&mut ()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].idrestaurant, i64::from(scraped.idrestaurant.unwrap()));
+        assert_eq!(stored[0].service, Service::Lunch);
+        assert_eq!(stored[0].foodies[0].course, Course::Main);
+
+        let statuses = statuses.find_all();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].outcome, ScrapeOutcome::Ok);
+        assert_eq!(statuses[0].meal_count, 1);
+        assert_eq!(statuses[0].day, chrono::NaiveDate::from_ymd_opt(2026, 10, 19).unwrap());
+
+        let suggestions = keywords.suggest(&mut (), "lasa", 5).await.unwrap();
+        assert!(suggestions.iter().any(|suggestion| suggestion.term == "lasagnes"));
+    }
+
+    #[test]
+    fn test_unavailable_menu() {
+        assert!(matches!(
+            unavailable_menu("Menu non communiqué"),
+            Some(MealError::NotPublished(_))
+        ));
+        assert!(matches!(
+            unavailable_menu("Le restaurant est fermé pour les vacances"),
+            Some(MealError::Closed(_))
+        ));
+        assert!(unavailable_menu("Fermé le samedi").is_none());
+    }
+}
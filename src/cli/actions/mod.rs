@@ -4,3 +4,10 @@ pub mod meals;
 pub mod restaurants;
 pub mod up;
 pub mod ping;
+pub mod search;
+pub mod suggest;
+pub mod keywords;
+pub mod find_dish;
+pub mod link;
+pub mod import;
+pub mod runs;
@@ -2,7 +2,10 @@ use std::process::ExitCode;
 
 use async_trait::async_trait;
 
-use crate::cli::{Action, ExitResult};
+use crate::{
+    cli::{Action, ExitResult},
+    models::crawl_run::RunCounts,
+};
 
 pub struct PingAction {
     pub url: String
@@ -18,9 +21,10 @@ impl PingAction {
     }
 }
 
+// runs before the database is reached, on no connection at all
 #[async_trait]
-impl Action for PingAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
+impl<C: Send> Action<C> for PingAction {
+    async fn execute(&self, _conn: &mut C, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
         match reqwest::get(self.url.clone()).await.map_err(|e|{
             ExitResult{
                 message: format!("{} is unreachable : {:?}", self.url, e),
@@ -1,44 +1,62 @@
-use std::{collections::HashMap, error::Error, process::ExitCode, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use regex::Regex;
 use scraper::{selectable::Selectable, Html, Selector};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
     cli::{Action, ExitResult},
+    geocoding::{GeocodeSource, Geocoder},
     models::{
-        keywords::{Category, KeywordService},
-        restaurants::{Restaurant, RestaurantService},
+        crawl_run::RunCounts,
+        geo::Coordinates,
+        keywords::{KeywordBatch, KeywordRepository},
+        restaurants::{Restaurant, RestaurantRepository, SOURCE_CROUS, SOURCE_IMPORT},
+        search::SearchIndex,
     },
 };
 
-pub struct RestaurantAction {
-    pub restaurant_service: Arc<RestaurantService>,
-    pub keyword_service: Arc<KeywordService>,
+pub struct RestaurantAction<R, K, I, C: Send> {
+    pub restaurant_service: Arc<R>,
+    pub keyword_service: Arc<K>,
+    pub search_service: Arc<I>,
+    // links the restaurants to schools and campuses, none on storages without links
+    pub link_action: Option<Arc<dyn Action<C>>>,
+    // the restaurants scraped by fetch, written by execute
+    fetched: Mutex<Option<Vec<Restaurant>>>,
 }
 
 pub struct RestaurantDetails {
     pub restaurant: String,
-    pub gps: String,
+    pub gps: Option<String>,
     pub hours: String,
+    pub address: Option<String>,
 }
 
-impl RestaurantAction {
+impl<R, K, I, C: Send> RestaurantAction<R, K, I, C> {
     pub fn new(
-        restaurant_service: Arc<RestaurantService>,
-        keyword_service: Arc<KeywordService>,
+        restaurant_service: Arc<R>,
+        keyword_service: Arc<K>,
+        search_service: Arc<I>,
+        link_action: Option<Arc<dyn Action<C>>>,
     ) -> Self {
         Self {
             restaurant_service,
             keyword_service,
+            search_service,
+            link_action,
+            fetched: Mutex::new(None),
         }
     }
-}
 
-#[async_trait]
-impl Action for RestaurantAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
+    // the restaurants listed on the CROUS website, with the details of their pages
+    async fn fetch_restaurants(&self, counts: &mut RunCounts) -> Result<Vec<Restaurant>, ExitResult> {
         let mut restaurants = match scrape().await {
             Ok(restaurants) => restaurants,
             Err(err) => {
@@ -56,6 +74,12 @@ impl Action for RestaurantAction {
                 });
             }
         };
+        counts.fetched += restaurants.len() as i32;
+
+        let geocoder = Geocoder::load().map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: e.to_string(),
+        })?;
 
         let mut restaurants_map = HashMap::new();
 
@@ -66,24 +90,40 @@ impl Action for RestaurantAction {
                 let restaurant_name = restaurant.name.clone();
                 restaurants_map.insert(restaurant_url.clone(), restaurant.clone());
                 tokio::spawn(async move {
-                    let coordinates = match scrape_coordinates(&restaurant_url).await {
-                        Ok(gps) => gps,
-                        Err(_) => RestaurantCoords {
-                            restaurant: "".to_string(),
-                            gps: "".to_string(),
-                        },
-                    };
-                    let hours = match scrape_hours(&restaurant_url).await {
-                        Ok(hours) => hours,
-                        Err(_) => {
-                            error!("{}: no hours", restaurant_name);
-                            "".to_string()
+                    // the coordinates, hours and address are all read from the restaurant page
+                    let (coordinates, hours, address) = match fetch_page(&restaurant_url).await {
+                        Ok(page) => {
+                            let document = Html::parse_document(&page);
+                            let coordinates = match find_coordinates(&document) {
+                                Ok(coordinates) => Some(coordinates.gps),
+                                Err(_) => None,
+                            };
+                            let hours = match find_hours(&document, &restaurant_url) {
+                                Ok(hours) => hours,
+                                Err(_) => {
+                                    error!("{}: no hours", restaurant_name);
+                                    "".to_string()
+                                }
+                            };
+                            let address = match find_address(&document) {
+                                Ok(address) => Some(address),
+                                Err(_) => {
+                                    error!("{}: no address", restaurant_name);
+                                    None
+                                }
+                            };
+                            (coordinates, hours, address)
+                        }
+                        Err(err) => {
+                            error!("{}: can't get restaurant page: {}", restaurant_name, err);
+                            (None, "".to_string(), None)
                         }
                     };
                     RestaurantDetails {
-                        restaurant: coordinates.restaurant,
-                        gps: coordinates.gps,
+                        restaurant: restaurant_url,
+                        gps: coordinates,
                         hours,
+                        address,
                     }
                 })
             })
@@ -94,59 +134,157 @@ impl Action for RestaurantAction {
         for task in tasks {
             let restaurant_details = task.await.unwrap();
 
-            if restaurant_details.gps.is_empty() {
-                continue;
-            }
             let restaurant = restaurants_map.get(restaurant_details.restaurant.as_str());
 
             if restaurant.is_none() {
+                counts.skipped += 1;
                 continue;
             }
 
             let mut restaurant = restaurant.unwrap().clone();
-            restaurant.gpscoord = Some(restaurant_details.gps);
             restaurant.hours = Some(restaurant_details.hours);
+            if restaurant_details.address.is_some() {
+                restaurant.address = restaurant_details.address;
+            }
+            match restaurant_details.gps {
+                Some(gps) => {
+                    restaurant.gpscoord = Some(gps);
+                    restaurant.geocode_source = Some(GeocodeSource::CrousMap.as_str().to_string());
+                    restaurant.geocode_confidence = Some(1.0);
+                }
+                // no map on the restaurant page, its address is looked up in the BAN instead
+                None => match (&geocoder, &restaurant.address) {
+                    (Some(geocoder), Some(address)) => match geocoder.geocode(address, None) {
+                        Some(geocoded) => {
+                            info!(
+                                "{}: geocoded from {} ({})",
+                                restaurant.name,
+                                geocoded.source.as_str(),
+                                geocoded.confidence
+                            );
+                            restaurant.gpscoord = Some(geocoded.coordinates.to_point());
+                            restaurant.geocode_source = Some(geocoded.source.as_str().to_string());
+                            restaurant.geocode_confidence = Some(geocoded.confidence);
+                        }
+                        None => warn!("{}: no coordinates, {} not found in the BAN", restaurant.name, address),
+                    },
+                    _ => warn!("{}: no coordinates", restaurant.name),
+                },
+            }
 
+            counts.parsed += 1;
             restaurants.push(restaurant);
         }
 
-        match self.restaurant_service.clear().await {
-            Ok(_) => (),
-            Err(err) => {
-                return Err(ExitResult {
-                    exit_code: ExitCode::from(2),
-                    message: format!("clear failed: {}", err),
-                });
-            }
-        }
+        Ok(restaurants)
+    }
 
-        for restaurant in restaurants {
-            match self.restaurant_service.create(restaurant).await {
-                Ok(restaurant) => {
-                    info!("Found restaurant {}", restaurant.name);
-                    for word in restaurant.name.split_whitespace() {
-                        self.keyword_service
-                            .create(
-                                word.to_string(),
-                                i64::from(restaurant.idrestaurant.unwrap()),
-                                Category::Restaurant,
-                            )
-                            .await
-                            .map_err(|err| {
-                                ExitResult {
-                                    exit_code: ExitCode::from(2),
-                                    message: format!("keyword insertion failed: {}", err),
-                                }
-                            })?;
-                    }
-                }
-                Err(err) => {
-                    return Err(ExitResult {
-                        exit_code: ExitCode::from(2),
-                        message: format!("restaurant insertion failed: {}", err),
-                    });
+    // what fetch scraped, for bootstrap to read the menus of restaurants not written yet
+    pub fn fetched(&self) -> Vec<Restaurant> {
+        self.fetched.lock().unwrap().clone().unwrap_or_default()
+    }
+}
+
+// runs on any storage the repositories support, Postgres, SQLite or memory
+#[async_trait]
+impl<C, R, K, I> Action<C> for RestaurantAction<R, K, I, C>
+where
+    C: Send,
+    R: RestaurantRepository<C>,
+    K: KeywordRepository<C>,
+    I: SearchIndex<C>,
+{
+    async fn fetch(&self, _conn: &mut C, counts: &mut RunCounts) -> Result<(), ExitResult> {
+        let restaurants = self.fetch_restaurants(counts).await?;
+        *self.fetched.lock().unwrap() = Some(restaurants);
+        Ok(())
+    }
+
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let fetched = self.fetched.lock().unwrap().take();
+        let restaurants = match fetched {
+            Some(restaurants) => restaurants,
+            None => self.fetch_restaurants(counts).await?,
+        };
+
+        // a url imported from a file stays with its imported row, rather than becoming a duplicate
+        let imported = self
+            .restaurant_service
+            .find_all(&mut *conn)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("restaurant insertion failed: {}", err),
+            })?
+            .into_iter()
+            .filter(|restaurant| restaurant.source == SOURCE_IMPORT)
+            .map(|restaurant| restaurant.url)
+            .collect::<HashSet<_>>();
+        let restaurants: Vec<Restaurant> = restaurants
+            .into_iter()
+            .filter(|restaurant| {
+                if imported.contains(&restaurant.url) {
+                    warn!("Skipping restaurant {}, its url {} is imported", restaurant.name, restaurant.url);
+                    counts.skipped += 1;
+                    return false;
                 }
+                true
+            })
+            .collect();
+
+        // upserted rather than recreated, so the menu statuses, suggestions and links of the
+        // restaurants still listed keep pointing at them
+        let urls: Vec<String> = restaurants.iter().map(|restaurant| restaurant.url.clone()).collect();
+        let upserted = self
+            .restaurant_service
+            .upsert_scraped(&mut *conn, restaurants)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("restaurant insertion failed: {}", err),
+            })?;
+
+        let mut keywords = KeywordBatch::default();
+        for (restaurant, inserted) in upserted {
+            info!("Found restaurant {}", restaurant.name);
+            if inserted {
+                counts.inserted += 1;
+            } else {
+                counts.updated += 1;
             }
+            self.keyword_service.extractor().collect_restaurant(&mut keywords, &restaurant);
+        }
+
+        let removed = self
+            .restaurant_service
+            .clear_unlisted(&mut *conn, &urls)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("clear failed: {}", err),
+            })?;
+        if removed > 0 {
+            info!("Removed {} restaurants no longer listed", removed);
+        }
+        self.keyword_service
+            .create_many(&mut *conn, keywords)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("keyword insertion failed: {}", err),
+            })?;
+
+        self.search_service.refresh(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("search index refresh failed: {}", err),
+        })?;
+        self.keyword_service.refresh_suggestions(&mut *conn).await.map_err(|err| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("suggestion index refresh failed: {}", err),
+        })?;
+        // new or moved restaurants need their links to schools and campuses
+        if let Some(link_action) = &self.link_action {
+            link_action.execute(&mut *conn, &mut RunCounts::default()).await?;
         }
 
         Ok(ExitResult {
@@ -158,6 +296,10 @@ impl Action for RestaurantAction {
     fn help(&self) -> &str {
         "scrape restaurants from the given restaurant"
     }
+
+    fn recorded(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Debug)]
@@ -222,6 +364,11 @@ async fn scrape() -> Result<Vec<Restaurant>, RestaurantError> {
             name: restaurant_name.to_string(),
             gpscoord: None,
             hours: None,
+            // the city is all we know until the restaurant page is scraped
+            address: Some(city.trim().to_string()),
+            geocode_source: None,
+            geocode_confidence: None,
+            source: SOURCE_CROUS.to_string(),
         });
     }
 
@@ -230,13 +377,14 @@ async fn scrape() -> Result<Vec<Restaurant>, RestaurantError> {
 
 #[derive(Debug)]
 struct RestaurantCoords {
-    restaurant: String,
     gps: String,
 }
 
-async fn scrape_coordinates(url: &str) -> Result<RestaurantCoords, Box<dyn std::error::Error>> {
-    let resp = reqwest::get(url).await?.text().await?;
-    let document = Html::parse_document(&resp);
+async fn fetch_page(url: &str) -> Result<String, reqwest::Error> {
+    reqwest::get(url).await?.text().await
+}
+
+fn find_coordinates(document: &Html) -> Result<RestaurantCoords, Box<dyn std::error::Error>> {
     let map_selector = Selector::parse("#map")?;
     let map_element = document.select(&map_selector).next();
     if map_element.is_none() {
@@ -257,15 +405,13 @@ async fn scrape_coordinates(url: &str) -> Result<RestaurantCoords, Box<dyn std::
 
     let long = long.unwrap();
 
+    let coordinates: Coordinates = format!("{},{}", lat, long).parse()?;
     Ok(RestaurantCoords {
-        restaurant: url.to_string(),
-        gps: format!("point({},{})", lat, long),
+        gps: coordinates.to_point(),
     })
 }
 
-async fn scrape_hours(url: &str) -> Result<String, Box<dyn Error>> {
-    let resp = reqwest::get(url).await?.text().await?;
-    let document = Html::parse_document(&resp);
+fn find_hours(document: &Html, url: &str) -> Result<String, Box<dyn Error>> {
     let hours_selector = Selector::parse(".info p")?;
     let hours = document.select(&hours_selector).next();
     if hours.is_none() {
@@ -279,6 +425,22 @@ async fn scrape_hours(url: &str) -> Result<String, Box<dyn Error>> {
     Ok(parse_hours(hours.as_str()))
 }
 
+// the address block has no dedicated class, so we take the first info paragraph that holds a
+// postcode
+fn find_address(document: &Html) -> Result<String, Box<dyn Error>> {
+    let address_selector = Selector::parse(".info p, address")?;
+    let postcode = Regex::new(r"\b\d{5}\b")?;
+    let address = document
+        .select(&address_selector)
+        .map(|element| element.text().collect::<Vec<_>>().join(" "))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|text| postcode.is_match(text));
+    match address {
+        Some(address) => Ok(address),
+        None => Err("no address found".into()),
+    }
+}
+
 fn parse_hours(raw_hour: &str) -> String {
     let raw_hour = raw_hour.to_lowercase();
     let re = Regex::new(r"du lundi au vendredi de |du lundi au jeudi de ").unwrap();
@@ -321,6 +483,83 @@ fn process_hour(hour: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        models::{
+            keywords::KeywordExtractor,
+            memory::{MemoryKeywords, MemoryRestaurants, MemorySearch},
+        },
+        text::synonyms::Synonyms,
+    };
+
+    fn restaurant(url: &str, name: &str, source: &str) -> Restaurant {
+        Restaurant {
+            idrestaurant: None,
+            url: url.to_string(),
+            name: name.to_string(),
+            gpscoord: None,
+            hours: None,
+            address: None,
+            geocode_source: None,
+            geocode_confidence: None,
+            source: source.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restaurants_in_memory() {
+        let restaurants = Arc::new(MemoryRestaurants::new());
+        let keywords = Arc::new(MemoryKeywords::new(KeywordExtractor::new(Arc::new(
+            Synonyms::load().unwrap(),
+        ))));
+        for (url, name, source) in [
+            ("https://crous/old", "Resto fermé", SOURCE_CROUS),
+            ("https://crous/triolet", "Resto U Triolet", SOURCE_CROUS),
+            ("https://ecole/cafet", "Cafet école", SOURCE_IMPORT),
+        ] {
+            restaurants.create(&mut (), restaurant(url, name, source)).await.unwrap();
+        }
+
+        let action: RestaurantAction<_, _, _, ()> = RestaurantAction::new(
+            restaurants.clone(),
+            keywords.clone(),
+            Arc::new(MemorySearch::default()),
+            None,
+        );
+        // what fetch would have scraped
+        *action.fetched.lock().unwrap() = Some(vec![
+            restaurant("https://crous/triolet", "Brasserie Triolet", SOURCE_CROUS),
+            restaurant("https://crous/vert-bois", "Resto U Vert-Bois", SOURCE_CROUS),
+            restaurant("https://ecole/cafet", "Cafétéria", SOURCE_CROUS),
+        ]);
+        let mut counts = RunCounts::default();
+        let Ok(result) = action.execute(&mut (), &mut counts).await else {
+            panic!("restaurants failed");
+        };
+        assert_eq!(result.message, "restaurants in database");
+        assert_eq!((counts.inserted, counts.updated, counts.skipped), (1, 1, 1));
+
+        // the listed restaurants are upserted and keep their id, the other scraped ones removed,
+        // the imported ones kept, even when listed
+        let mut stored: Vec<(Option<i32>, String)> = restaurants
+            .find_all(&mut ())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|restaurant| (restaurant.idrestaurant, restaurant.name))
+            .collect();
+        stored.sort();
+        assert_eq!(
+            stored,
+            vec![
+                (Some(2), "Brasserie Triolet".to_string()),
+                (Some(3), "Cafet école".to_string()),
+                (Some(4), "Resto U Vert-Bois".to_string()),
+            ]
+        );
+
+        let suggestions = keywords.suggest(&mut (), "triol", 5).await.unwrap();
+        assert!(!suggestions.is_empty());
+    }
 
     #[tokio::test]
     async fn test_scrape() {
@@ -330,9 +569,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_scrape_coordinates() {
-        let gps =
-            scrape_coordinates("https://www.crous-montpellier.fr/restaurant/brasserie-veyrassi-2/")
-                .await;
+        let page = fetch_page("https://www.crous-montpellier.fr/restaurant/brasserie-veyrassi-2/")
+            .await
+            .unwrap();
+        let gps = find_coordinates(&Html::parse_document(&page));
 
         if gps.is_err() {
             println!("{:?}", gps);
@@ -340,4 +580,13 @@ mod tests {
 
         assert!(gps.is_ok());
     }
+
+    #[test]
+    fn test_find_coordinates_point() {
+        let page = r#"<div id="map" data-lat="43.6323" data-lon="3.8652"></div>"#;
+        let gps = find_coordinates(&Html::parse_document(page)).unwrap().gps;
+
+        assert_eq!(gps, "(43.6323,3.8652)");
+        assert_eq!(Coordinates::from_point(&gps), Some(Coordinates { lat: 43.6323, lon: 3.8652 }));
+    }
 }
@@ -0,0 +1,98 @@
+use std::{process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult, RunsCommand},
+    models::crawl_run::{CrawlRun, CrawlRunRepository, RunCounts},
+};
+
+pub struct RunsAction<R> {
+    pub run_service: Arc<R>,
+    pub command: RunsCommand,
+}
+
+impl<R> RunsAction<R> {
+    pub fn new(run_service: Arc<R>, command: RunsCommand) -> Self {
+        Self {
+            run_service,
+            command,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send, R: CrawlRunRepository<C>> Action<C> for RunsAction<R> {
+    async fn execute(&self, conn: &mut C, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        match &self.command {
+            RunsCommand::List { action, limit } => {
+                let runs = self
+                    .run_service
+                    .find_all(&mut *conn, action.as_deref(), *limit)
+                    .await
+                    .map_err(|e| ExitResult {
+                        exit_code: ExitCode::from(2),
+                        message: format!("can't list runs: {}", e),
+                    })?;
+                for run in runs.iter() {
+                    info!(
+                        "[{}] {} {} {} {} in {}s, {}: {}",
+                        run.idrun,
+                        run.started_at.format("%Y-%m-%d %H:%M:%S"),
+                        run.action,
+                        run.region,
+                        run.status.as_str(),
+                        duration(run),
+                        summary(&run.counts),
+                        run.message
+                    );
+                }
+                Ok(ExitResult {
+                    exit_code: ExitCode::from(0),
+                    message: format!("{} runs", runs.len()),
+                })
+            }
+            RunsCommand::Show { id } => {
+                let run = self
+                    .run_service
+                    .find(&mut *conn, *id)
+                    .await
+                    .map_err(|e| ExitResult {
+                        exit_code: ExitCode::from(2),
+                        message: format!("can't read run {}: {}", id, e),
+                    })?
+                    .ok_or_else(|| ExitResult {
+                        exit_code: ExitCode::from(2),
+                        message: format!("run {} not found", id),
+                    })?;
+                info!("action: {}", run.action);
+                info!("region: {}", run.region);
+                info!("started: {}", run.started_at.to_rfc3339());
+                info!("ended: {} ({}s)", run.ended_at.to_rfc3339(), duration(&run));
+                info!("status: {}", run.status.as_str());
+                info!("items: {}", summary(&run.counts));
+                info!("message: {}", run.message);
+                Ok(ExitResult {
+                    exit_code: ExitCode::from(0),
+                    message: format!("run {}", run.idrun),
+                })
+            }
+        }
+    }
+
+    fn help(&self) -> &str {
+        "show the runs recorded in the crawl_run ledger"
+    }
+}
+
+fn duration(run: &CrawlRun) -> f64 {
+    run.ended_at.signed_duration_since(run.started_at).num_milliseconds() as f64 / 1000.0
+}
+
+fn summary(counts: &RunCounts) -> String {
+    format!(
+        "{} fetched, {} parsed, {} inserted, {} updated, {} skipped, {} errors",
+        counts.fetched, counts.parsed, counts.inserted, counts.updated, counts.skipped, counts.errors
+    )
+}
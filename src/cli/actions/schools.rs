@@ -1,46 +1,54 @@
+use std::env;
 use std::fmt::Display;
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 use async_trait::async_trait;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
 
 use crate::cli::Action;
 use crate::cli::ExitResult;
+use crate::geocoding::GeocodeSource;
+use crate::geocoding::Geocoded;
+use crate::geocoding::Geocoder;
+use crate::models::crawl_run::RunCounts;
+use crate::models::geo::BoundingBox;
+use crate::models::geo::Coordinates;
 use crate::models::schools::School;
-use crate::models::schools::SchoolService;
+use crate::models::schools::SchoolRepository;
+use crate::opendatasoft::Client;
+use crate::opendatasoft::Query;
+use crate::selection::Selection;
+use crate::selection::Verdict;
 
 // Documentation here : https://www.herault-data.fr/explore/dataset/onisep-etablissements-denseignement-superieur-herault/api/
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct HeraultData {
-    #[serde(rename = "total_count")]
-    pub total_count: i64,
-    pub results: Vec<ApiSchool>,
-}
+const DEFAULT_PORTAL: &str = "https://www.herault-data.fr";
+const DEFAULT_DATASET: &str = "onisep-etablissements-denseignement-superieur-herault";
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiSchool {
     #[serde(rename = "code_uai")]
-    pub code_uai: String,
+    pub code_uai: Option<String>,
     #[serde(rename = "ndeg_siret")]
     pub ndeg_siret: Option<f64>,
     #[serde(rename = "type_d_etablissement")]
-    pub type_d_etablissement: String,
+    pub type_d_etablissement: Option<String>,
     pub nom: String,
     pub sigle: Option<String>,
-    pub statut: String,
+    pub statut: Option<String>,
     pub tutelle: Option<String>,
     pub universite: Option<String>,
     #[serde(rename = "boite_postale")]
     pub boite_postale: Option<String>,
-    pub adresse: String,
-    pub cp: f64,
-    pub commune: String,
-    pub telephone: String,
+    pub adresse: Option<String>,
+    pub cp: Option<f64>,
+    pub commune: Option<String>,
+    pub telephone: Option<String>,
     #[serde(rename = "debut_portes_ouvertes")]
     pub debut_portes_ouvertes: Option<String>,
     #[serde(rename = "fin_portes_ouvertes")]
@@ -48,9 +56,12 @@ pub struct ApiSchool {
     #[serde(rename = "commentaires_portes_ouvertes")]
     pub commentaires_portes_ouvertes: Option<String>,
     #[serde(rename = "lien_site_onisep_fr")]
-    pub lien_site_onisep_fr: String,
-    #[serde(rename = "point_geo")]
-    pub point_geo: PointGeo,
+    pub lien_site_onisep_fr: Option<String>,
+    #[serde(rename = "point_geo", default)]
+    pub point_geo: Option<PointGeo>,
+    // coordinates of the school with their origin: point_geo, or the BAN when it is missing
+    #[serde(skip)]
+    pub geocoded: Option<Geocoded>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -66,53 +77,278 @@ impl Display for PointGeo {
     }
 }
 
-pub struct SchoolAction {
-    pub school_service: Arc<SchoolService>,
+// where schools are read from: any OpenDataSoft dataset with the ONISEP columns, such as the
+// Hérault extract (default) or the national dataset, narrowed to some departments or to an area
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchoolSource {
+    pub portal: String,
+    pub dataset: String,
+    pub departments: Vec<String>,
+    pub bbox: Option<BoundingBox>,
 }
 
-impl SchoolAction {
-    pub fn new(school_service: Arc<SchoolService>) -> Self {
-        Self { school_service }
+impl SchoolSource {
+    // reads SCHOOLS_PORTAL, SCHOOLS_DATASET, SCHOOLS_DEPARTMENTS ("34,30,66") and SCHOOLS_BBOX
+    // ("min_lat,min_lon,max_lat,max_lon")
+    pub fn from_env() -> Result<Self, String> {
+        let departments: Vec<String> = match env::var("SCHOOLS_DEPARTMENTS") {
+            Ok(departments) => departments
+                .split(',')
+                .map(|department| department.trim().to_uppercase())
+                .filter(|department| !department.is_empty())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if let Some(department) = departments
+            .iter()
+            .find(|department| postcode_range(department).is_none())
+        {
+            return Err(format!("SCHOOLS_DEPARTMENTS: unknown department {}", department));
+        }
+        let bbox = match env::var("SCHOOLS_BBOX") {
+            Ok(bbox) => Some(bbox.parse::<BoundingBox>().map_err(|e| format!("SCHOOLS_BBOX: {}", e))?),
+            Err(_) => None,
+        };
+        Ok(Self {
+            portal: env::var("SCHOOLS_PORTAL").unwrap_or(DEFAULT_PORTAL.to_string()),
+            dataset: env::var("SCHOOLS_DATASET").unwrap_or(DEFAULT_DATASET.to_string()),
+            departments,
+            bbox,
+        })
+    }
+
+    // departments are applied by the portal, on the postcode since the datasets don't all have a
+    // department column. So is the bounding box, unless schools without point_geo can be
+    // geocoded
+    fn query(&self, geocoding: bool) -> Query {
+        let mut query = Query::new(&self.dataset);
+        if let Some(condition) = departments_condition(&self.departments) {
+            query = query.filter(&condition);
+        }
+        match self.bbox {
+            Some(bbox) if !geocoding => query.filter(&format!(
+                "in_bbox(point_geo, {}, {}, {}, {})",
+                bbox.min.lat, bbox.min.lon, bbox.max.lat, bbox.max.lon
+            )),
+            _ => query,
+        }
+    }
+
+    fn keeps(&self, school: &ApiSchool) -> bool {
+        match (self.bbox, school.geocoded) {
+            (Some(bbox), Some(geocoded)) => bbox.contains(&geocoded.coordinates),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
     }
 }
 
-#[async_trait]
-impl Action for SchoolAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
-        const BASE_URL: &str = "https://www.herault-data.fr/api/explore/v2.1/catalog/datasets/onisep-etablissements-denseignement-superieur-herault/records";
-
-        // Fetch both pages concurrently
-        let (url_page1, url_page2) = (
-            format!("{}?limit=100", BASE_URL),
-            format!("{}?limit=100&offset=100", BASE_URL),
-        );
-        let (page1, page2) = tokio::try_join!(
-            fetch_schools_page(&url_page1),
-            fetch_schools_page(&url_page2)
-        )?;
+// postcodes of a department, from its first one included to its last one excluded: two digits,
+// three overseas, 2A/2B for Corsica
+fn postcode_range(department: &str) -> Option<(u32, u32)> {
+    match department {
+        "2A" => Some((20000, 20200)),
+        "2B" => Some((20200, 21000)),
+        department if !department.chars().all(|c| c.is_ascii_digit()) => None,
+        department if department.len() == 2 => {
+            let code: u32 = department.parse().ok()?;
+            Some((code * 1000, (code + 1) * 1000))
+        }
+        department if department.len() == 3 && department.starts_with("97") => {
+            let code: u32 = department.parse().ok()?;
+            Some((code * 100, (code + 1) * 100))
+        }
+        _ => None,
+    }
+}
+
+// ODSQL condition keeping the schools of any of the departments
+fn departments_condition(departments: &[String]) -> Option<String> {
+    let ranges: Vec<String> = departments
+        .iter()
+        .filter_map(|department| postcode_range(department))
+        .map(|(first, last)| format!("cp >= {} AND cp < {}", first, last))
+        .collect();
+    if ranges.is_empty() {
+        return None;
+    }
+    Some(
+        ranges
+            .iter()
+            .map(|range| format!("({})", range))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    )
+}
+
+pub struct SchoolAction<S, C: Send> {
+    pub school_service: Arc<S>,
+    // links the synced schools to restaurants, none when there is nothing to link them to, as in
+    // dry runs
+    pub link_action: Option<Arc<dyn Action<C>>>,
+    pub source: SchoolSource,
+    // only lists the schools each rule kept or dropped, nothing is written
+    pub dry_run: bool,
+    // the schools of the selected area read by fetch, synced by execute
+    fetched: Mutex<Option<Vec<ApiSchool>>>,
+}
+
+impl<S, C: Send> SchoolAction<S, C> {
+    pub fn new(
+        school_service: Arc<S>,
+        link_action: Option<Arc<dyn Action<C>>>,
+        source: SchoolSource,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            school_service,
+            link_action,
+            source,
+            dry_run,
+            fetched: Mutex::new(None),
+        }
+    }
 
-        // Process both pages
-        let schools: Vec<ApiSchool> = filter_public_schools(page1.results)
+    // every page of the dataset, geocoded and restricted to the selected area
+    async fn fetch_schools(&self, counts: &mut RunCounts) -> Result<Vec<ApiSchool>, ExitResult> {
+        let geocoder = Geocoder::load().map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: e.to_string(),
+        })?;
+
+        let records: Vec<ApiSchool> = Client::new(&self.source.portal)
+            .records(&self.source.query(geocoder.is_some()))
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::FAILURE,
+                message: format!("schools fetch failed: {}", e),
+            })?;
+        let fetched = records.len();
+        // schools are synced on their UAI, a record without one can't be
+        let (records, without_uai): (Vec<ApiSchool>, Vec<ApiSchool>) = records
             .into_iter()
-            .chain(filter_public_schools(page2.results))
+            .partition(|school| non_empty(school.code_uai.clone()).is_some());
+        if !without_uai.is_empty() {
+            warn!(
+                "{}: {} schools without a UAI skipped, {}",
+                self.source.dataset,
+                without_uai.len(),
+                without_uai.iter().map(|school| school.nom.as_str()).collect::<Vec<_>>().join(", ")
+            );
+        }
+        let records: Vec<ApiSchool> = records
+            .into_iter()
+            .map(|mut school| {
+                school.geocoded = geocode(&school, geocoder.as_ref());
+                school
+            })
+            .filter(|school| self.source.keeps(school))
             .collect();
+        info!(
+            "{}: {} schools fetched, {} in the selected area",
+            self.source.dataset,
+            fetched,
+            records.len()
+        );
+        counts.fetched += fetched as i32;
+        counts.skipped += (fetched - records.len()) as i32;
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl<C: Send, S: SchoolRepository<C>> Action<C> for SchoolAction<S, C> {
+    async fn fetch(&self, _conn: &mut C, counts: &mut RunCounts) -> Result<(), ExitResult> {
+        let records = self.fetch_schools(counts).await?;
+        *self.fetched.lock().unwrap() = Some(records);
+        Ok(())
+    }
+
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let fetched = self.fetched.lock().unwrap().take();
+        let records = match fetched {
+            Some(records) => records,
+            None => self.fetch_schools(counts).await?,
+        };
 
-        // Clear existing schools
-        self.school_service.clear().await.map_err(|e| ExitResult {
-            exit_code: ExitCode::FAILURE,
-            message: format!("school clear failed: {}", e),
+        let selection = Selection::load().map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("school rules: {}", e),
         })?;
+        info!("school rules v{} loaded", selection.version());
+
+        let selected = records.len();
+        let verdicts: Vec<(ApiSchool, Verdict)> = records
+            .into_iter()
+            .map(|school| {
+                let verdict = selection.evaluate(&school);
+                (school, verdict)
+            })
+            .collect();
+        for rule in selection.rule_ids() {
+            let decided: Vec<&(ApiSchool, Verdict)> = verdicts
+                .iter()
+                .filter(|(_, verdict)| verdict.rule == rule)
+                .collect();
+            if decided.is_empty() {
+                continue;
+            }
+            info!(
+                "rule {}: {} kept, {} dropped",
+                rule,
+                decided.iter().filter(|(_, verdict)| verdict.kept).count(),
+                decided.iter().filter(|(_, verdict)| !verdict.kept).count()
+            );
+            if self.dry_run {
+                for (school, verdict) in decided {
+                    info!(
+                        "  {} {} {} ({}, {})",
+                        if verdict.kept { "kept" } else { "dropped" },
+                        school.code_uai.as_deref().unwrap_or_default(),
+                        school.nom,
+                        school.statut.as_deref().unwrap_or_default(),
+                        school.type_d_etablissement.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+        }
+
+        let schools: Vec<ApiSchool> = verdicts
+            .into_iter()
+            .filter(|(_, verdict)| verdict.kept)
+            .map(|(school, _)| school)
+            .collect();
+        counts.parsed += schools.len() as i32;
+        counts.skipped += (selected - schools.len()) as i32;
+        if self.dry_run {
+            return Ok(ExitResult {
+                exit_code: ExitCode::SUCCESS,
+                message: format!("dry run: {} schools would be synced", schools.len()),
+            });
+        }
 
-        // Insert new schools
-        for school_data in schools {
-            let school = convert_to_school(school_data);
-            self.school_service
-                .create(school)
-                .await
-                .map_err(|e| ExitResult {
-                    exit_code: ExitCode::FAILURE,
-                    message: format!("school insertion failed: {}", e),
-                })?;
+        // Upsert schools on their UAI code, the missing ones are deactivated
+        if schools.is_empty() {
+            warn!("{}: no school to sync, none is deactivated", self.source.dataset);
+        }
+        let report = self
+            .school_service
+            .sync(&mut *conn, schools.into_iter().map(convert_to_school).collect())
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::FAILURE,
+                message: format!("school sync failed: {}", e),
+            })?;
+        info!(
+            "schools: {} inserted, {} updated, {} deactivated",
+            report.inserted, report.updated, report.deactivated
+        );
+        counts.inserted += report.inserted as i32;
+        counts.updated += report.updated as i32;
+
+        // Link the new schools and their campuses to restaurants
+        if let Some(link_action) = &self.link_action {
+            link_action.execute(&mut *conn, &mut RunCounts::default()).await?;
         }
 
         Ok(ExitResult {
@@ -124,37 +360,159 @@ impl Action for SchoolAction {
     fn help(&self) -> &str {
         "scrape schools from the given school"
     }
-}
 
-async fn fetch_schools_page(url: &str) -> Result<HeraultData, ExitResult> {
-    let response = reqwest::get(url).await.map_err(|e| ExitResult {
-        exit_code: ExitCode::FAILURE,
-        message: format!("reqwest error: {}", e),
-    })?;
-
-    let data = response.text().await.map_err(|e| ExitResult {
-        exit_code: ExitCode::FAILURE,
-        message: format!("reqwest error: {}", e),
-    })?;
-
-    serde_json::from_str(&data).map_err(|e| ExitResult {
-        exit_code: ExitCode::FAILURE,
-        message: format!("serde_json error: {}", e),
-    })
+    fn recorded(&self) -> bool {
+        true
+    }
 }
 
-fn filter_public_schools(schools: Vec<ApiSchool>) -> Vec<ApiSchool> {
-    schools
-        .into_iter()
-        .filter(|school| school.statut.contains("Public"))
-        .collect()
+// ONISEP coordinates when given, the BAN otherwise
+fn geocode(school: &ApiSchool, geocoder: Option<&Geocoder>) -> Option<Geocoded> {
+    if let Some(point_geo) = &school.point_geo {
+        return Some(Geocoded {
+            coordinates: Coordinates {
+                lat: point_geo.lat,
+                lon: point_geo.lon,
+            },
+            source: GeocodeSource::Onisep,
+            confidence: 1.0,
+        });
+    }
+    let address = school.adresse.as_deref()?;
+    let geocoded = geocoder?.geocode(address, postcode(school).as_deref());
+    match geocoded {
+        Some(geocoded) => info!(
+            "{}: geocoded from {} ({})",
+            school.nom,
+            geocoded.source.as_str(),
+            geocoded.confidence
+        ),
+        None => warn!("{}: no coordinates, {} not found in the BAN", school.nom, address),
+    }
+    geocoded
 }
 
 fn convert_to_school(school_data: ApiSchool) -> School {
+    let postcode = postcode(&school_data);
     School {
         idschool: 0,
+        code_uai: school_data.code_uai.unwrap_or_default().trim().to_uppercase(),
         name: school_data.sigle.unwrap_or(school_data.nom.clone()),
-        coords: school_data.point_geo.to_string(),
+        coords: school_data.geocoded.map(|geocoded| geocoded.coordinates.to_point()),
+        geocode_source: school_data.geocoded.map(|geocoded| geocoded.source.as_str().to_string()),
+        geocode_confidence: school_data.geocoded.map(|geocoded| geocoded.confidence),
         long_name: school_data.nom,
+        postcode,
+        address: non_empty(school_data.adresse),
+        commune: non_empty(school_data.commune),
+        phone: non_empty(school_data.telephone),
+        school_type: non_empty(school_data.type_d_etablissement),
+        website: non_empty(school_data.lien_site_onisep_fr),
+    }
+}
+
+fn postcode(school: &ApiSchool) -> Option<String> {
+    school.cp.map(|cp| format!("{:05}", cp as u32))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    let value = value?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::models::memory::MemorySchools;
+
+    // a portal answering every request with the same page of records
+    async fn portal(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_without_database() {
+        let portal = portal(
+            r#"{"total_count": 3, "results": [
+                {"code_uai": "0341234A", "nom": "Université", "statut": "Public", "cp": 34090, "lien_site_onisep_fr": ""},
+                {"code_uai": "0345678B", "nom": "École privée", "statut": "Privé hors contrat", "cp": 34000, "lien_site_onisep_fr": ""},
+                {"code_uai": null, "nom": "Antenne", "statut": "Public", "cp": 34000}
+            ]}"#,
+        )
+        .await;
+        let source = SchoolSource {
+            portal,
+            dataset: "onisep".to_string(),
+            departments: vec!["34".to_string()],
+            bbox: None,
+        };
+        let action: SchoolAction<MemorySchools, ()> =
+            SchoolAction::new(Arc::new(MemorySchools::new()), None, source, true);
+        let mut counts = RunCounts::default();
+        let Ok(result) = action.execute(&mut (), &mut counts).await else {
+            panic!("the dry run needs no database");
+        };
+        assert_eq!(result.message, "dry run: 1 schools would be synced");
+        assert_eq!(counts.fetched, 3);
+        assert_eq!(counts.skipped, 2);
+        assert_eq!(counts.inserted, 0);
+    }
+
+    #[test]
+    fn test_postcode_range() {
+        assert_eq!(postcode_range("34"), Some((34000, 35000)));
+        assert_eq!(postcode_range("01"), Some((1000, 2000)));
+        assert_eq!(postcode_range("2A"), Some((20000, 20200)));
+        assert_eq!(postcode_range("2B"), Some((20200, 21000)));
+        assert_eq!(postcode_range("974"), Some((97400, 97500)));
+        assert_eq!(postcode_range("HERAULT"), None);
+        assert_eq!(postcode_range("340"), None);
+    }
+
+    #[test]
+    fn test_departments_condition() {
+        assert_eq!(departments_condition(&[]), None);
+        assert_eq!(
+            departments_condition(&["34".to_string(), "2A".to_string()]),
+            Some("(cp >= 34000 AND cp < 35000) OR (cp >= 20000 AND cp < 20200)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_school_with_null_fields() {
+        let school: ApiSchool = serde_json::from_str(
+            r#"{"code_uai": "0341234A", "nom": "École", "lien_site_onisep_fr": "",
+                "adresse": null, "cp": null, "commune": null, "telephone": null,
+                "statut": null, "type_d_etablissement": null}"#,
+        )
+        .unwrap();
+        let school = convert_to_school(school);
+        assert_eq!(school.postcode, None);
+        assert_eq!(school.address, None);
+        assert_eq!(school.website, None);
+
+        let school: ApiSchool = serde_json::from_str(r#"{"code_uai": "0341234A", "nom": "École"}"#).unwrap();
+        assert_eq!(school.lien_site_onisep_fr, None);
+        assert_eq!(convert_to_school(school).website, None);
     }
 }
@@ -0,0 +1,60 @@
+use std::{process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use sqlx::PgConnection;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult},
+    models::{crawl_run::RunCounts, search::SearchService},
+};
+
+pub struct SearchAction {
+    pub search_service: Arc<SearchService>,
+    pub query: String,
+    pub limit: i64,
+}
+
+impl SearchAction {
+    pub fn new(search_service: Arc<SearchService>, query: String, limit: i64) -> Self {
+        Self {
+            search_service,
+            query,
+            limit,
+        }
+    }
+}
+
+#[async_trait]
+impl Action for SearchAction {
+    async fn execute(&self, conn: &mut PgConnection, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let hits = self
+            .search_service
+            .search(&mut *conn, &self.query, self.limit)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("search failed: {}", e),
+            })?;
+
+        for hit in hits.iter() {
+            info!(
+                "{:.3} [{}] {} - {} ({})",
+                hit.rank,
+                hit.idrestaurant,
+                hit.name,
+                hit.address.clone().unwrap_or_default(),
+                hit.url
+            );
+        }
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: format!("{} restaurants found for \"{}\"", hits.len(), self.query),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "search restaurants by name, address and dishes served"
+    }
+}
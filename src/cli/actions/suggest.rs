@@ -0,0 +1,55 @@
+use std::{process::ExitCode, sync::Arc};
+
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::{
+    cli::{Action, ExitResult},
+    models::{crawl_run::RunCounts, keywords::KeywordRepository},
+};
+
+pub struct SuggestAction<K> {
+    pub keyword_service: Arc<K>,
+    pub prefix: String,
+    pub limit: i64,
+}
+
+impl<K> SuggestAction<K> {
+    pub fn new(keyword_service: Arc<K>, prefix: String, limit: i64) -> Self {
+        Self {
+            keyword_service,
+            prefix,
+            limit,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send, K: KeywordRepository<C>> Action<C> for SuggestAction<K> {
+    async fn execute(&self, conn: &mut C, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        let suggestions = self
+            .keyword_service
+            .suggest(&mut *conn, &self.prefix, self.limit)
+            .await
+            .map_err(|e| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("suggest failed: {}", e),
+            })?;
+
+        for suggestion in suggestions.iter() {
+            info!(
+                "{:.3} [{}] {} ({} occurrences)",
+                suggestion.score, suggestion.category, suggestion.term, suggestion.occurrences
+            );
+        }
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: format!("{} suggestions for \"{}\"", suggestions.len(), self.prefix),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "suggest restaurants, foods and meals matching a prefix, even misspelled"
+    }
+}
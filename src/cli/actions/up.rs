@@ -1,19 +1,21 @@
-use std::{process::ExitCode, sync::Arc};
+use std::process::ExitCode;
 
 use async_trait::async_trait;
-use sqlx::PgPool;
+use sqlx::PgConnection;
 
-use crate::cli::{Action, ExitResult};
+use crate::{
+    cli::{Action, ExitResult},
+    models::crawl_run::RunCounts,
+};
 
-pub struct UpAction {
-    pub pool: Arc<PgPool>,
-}
+pub struct UpAction {}
 
 #[async_trait]
 impl Action for UpAction {
-    async fn execute(&self) -> Result<ExitResult, ExitResult> {
+    async fn execute(&self, conn: &mut PgConnection, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
         sqlx::migrate!("./migrations/")
-            .run(self.pool.as_ref())
+            // run_direct, since run can't take the connection of an async_trait method
+            .run_direct(&mut *conn)
             .await
             .map_err(|err| ExitResult {
                 exit_code: ExitCode::from(2),
@@ -30,3 +32,27 @@ impl Action for UpAction {
         return "run the migrations"
     }
 }
+
+// SQLite has its own schema, coordinates and JSON are stored differently
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl Action<sqlx::SqliteConnection> for UpAction {
+    async fn execute(&self, conn: &mut sqlx::SqliteConnection, _counts: &mut RunCounts) -> Result<ExitResult, ExitResult> {
+        sqlx::migrate!("./migrations-sqlite/")
+            .run_direct(&mut *conn)
+            .await
+            .map_err(|err| ExitResult {
+                exit_code: ExitCode::from(2),
+                message: format!("migration failed: {}", err),
+            })?;
+
+        Ok(ExitResult {
+            exit_code: ExitCode::from(0),
+            message: "migration done".to_string(),
+        })
+    }
+
+    fn help(&self) -> &str {
+        "run the migrations"
+    }
+}
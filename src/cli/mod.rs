@@ -1,7 +1,14 @@
-use std::{collections::HashMap, process::ExitCode};
+use std::{collections::HashMap, mem::Discriminant, process::ExitCode, sync::Arc};
 
 use async_trait::async_trait;
 use clap::{Parser, Subcommand};
+use sqlx::{Database, PgConnection, Pool, Postgres};
+use tracing::{info, warn};
+
+use crate::models::{
+    crawl_run::{CrawlRun, CrawlRunRepository, RunCounts, RunStatus},
+    geo::Coordinates,
+};
 pub struct ExitResult {
     pub exit_code: ExitCode,
     pub message: String,
@@ -9,14 +16,39 @@ pub struct ExitResult {
 
 pub mod actions;
 
+// actions write through the connection they are given, the transaction of the command, so that
+// an action calling others stays atomic. Actions only relying on repositories run on any
+// connection, including none (`()`) with the in-memory repositories. The items an action goes
+// through are added to counts, recorded with its outcome in the crawl_run ledger
 #[async_trait]
-pub trait Action {
-    async fn execute(&self) -> Result<ExitResult, ExitResult>;
+pub trait Action<C: Send = PgConnection>: Send + Sync {
+    // reads the websites the action needs before the transaction is opened, on a connection
+    // outside of it, so that no lock is held while pages load. The action keeps what it fetched
+    // for execute, which fetches by itself when it wasn't
+    async fn fetch(&self, _conn: &mut C, _counts: &mut RunCounts) -> Result<(), ExitResult> {
+        Ok(())
+    }
+
+    async fn execute(&self, conn: &mut C, counts: &mut RunCounts) -> Result<ExitResult, ExitResult>;
     fn help(&self) -> &str;
+
+    // whether the runs of the action are written to the ledger, only the ones changing the data
+    // opt in, reads like search or runs aren't worth a row
+    fn recorded(&self) -> bool {
+        false
+    }
 }
 
-pub struct Cli {
-    actions: HashMap<Command, Box<dyn Action>>,
+// actions are registered per command kind, so commands holding arguments can be subscribed
+// before their arguments are known. Each command fetches first, then writes in one transaction,
+// committed only when its action succeeds, on Postgres unless the sqlite feature picked another
+// database. Each run is then recorded in the crawl_run ledger, failed ones included
+pub struct Cli<DB: Database = Postgres> {
+    pool: Arc<Pool<DB>>,
+    actions: HashMap<Discriminant<Command>, Box<dyn Action<DB::Connection>>>,
+    run_service: Arc<dyn CrawlRunRepository<DB::Connection>>,
+    // region of the CROUS website, written with each run
+    region: String,
 }
 
 #[derive(Debug, Parser)]
@@ -29,14 +61,87 @@ pub struct App{
     pub ping: bool,
 }
 
-#[derive(Debug, Subcommand, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Subcommand, PartialEq)]
 pub enum Command {
     Restaurants,
     Meals,
     Up,
     Bootstrap,
     Ping,
-    Schools
+    Schools {
+        /// list the schools kept or dropped by each selection rule, without writing them
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    Link,
+    /// search restaurants by name, address and dishes served
+    Search {
+        query: String,
+        #[clap(short, long, default_value_t = 10)]
+        limit: i64,
+    },
+    /// suggest restaurants, foods and meals matching a prefix, even misspelled
+    Suggest {
+        prefix: String,
+        #[clap(short, long, default_value_t = 10)]
+        limit: i64,
+    },
+    /// find the restaurants serving a dish on a given day, by default today
+    FindDish {
+        query: String,
+        #[clap(short, long)]
+        date: Option<chrono::NaiveDate>,
+        /// lat,lon of the point the restaurants are sorted from
+        #[clap(long)]
+        near: Option<Coordinates>,
+    },
+    /// maintain the keywords used by suggestions
+    Keywords {
+        #[clap(subcommand)]
+        command: KeywordsCommand,
+    },
+    /// import schools or restaurants missing from the scraped sources
+    Import {
+        #[clap(subcommand)]
+        command: ImportCommand,
+    },
+    /// show the past runs of every command, with their outcome and item counts
+    Runs {
+        #[clap(subcommand)]
+        command: RunsCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+pub enum KeywordsCommand {
+    /// delete the keywords not seen for a while that no restaurant or meal supports anymore
+    Gc {
+        /// age such as 30d, 12h or 2w
+        #[clap(long, default_value = "30d", value_parser = actions::keywords::parse_age)]
+        older_than: chrono::Duration,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+pub enum ImportCommand {
+    /// upsert schools from a CSV or GeoJSON file, on their UAI code
+    Schools { file: std::path::PathBuf },
+    /// upsert restaurants from a CSV or GeoJSON file, on their url
+    Restaurants { file: std::path::PathBuf },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+pub enum RunsCommand {
+    /// the latest runs first
+    List {
+        /// only the runs of this command, such as meals
+        #[clap(long)]
+        action: Option<String>,
+        #[clap(short, long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// every detail of a run
+    Show { id: i64 },
 }
 
 impl Command {
@@ -46,23 +151,43 @@ impl Command {
             Self::Meals => "meals",
             Self::Up => "up",
             Self::Ping => "ping",
-            Self::Bootstrap => "bootstap",
-            Self::Schools => "schools",
+            Self::Bootstrap => "bootstrap",
+            Self::Schools { .. } => "schools",
+            Self::Link => "link",
+            Self::Search { .. } => "search",
+            Self::Suggest { .. } => "suggest",
+            Self::Keywords { .. } => "keywords",
+            Self::FindDish { .. } => "find-dish",
+            Self::Import { .. } => "import",
+            Self::Runs { .. } => "runs",
         }
     }
 }
 
-impl Cli {
-    pub fn new() -> Self {
+impl<DB: Database> Cli<DB> {
+    pub fn new(
+        pool: Arc<Pool<DB>>,
+        run_service: Arc<dyn CrawlRunRepository<DB::Connection>>,
+        region: String,
+    ) -> Self {
         Self {
+            pool,
             actions: HashMap::new(),
+            run_service,
+            region,
         }
     }
 
     pub async fn execute(&mut self, app: App) -> Result<ExitResult, ExitResult> {
-        match self.actions.get(&app.action) {
+        match self.actions.get(&std::mem::discriminant(&app.action)) {
             Some(command) => {
-                command.execute().await
+                let started_at = chrono::Utc::now();
+                let mut counts = RunCounts::default();
+                let result = self.run(command.as_ref(), &mut counts).await;
+                if command.recorded() {
+                    self.record(&app.action, started_at, counts, &result).await;
+                }
+                result
             },
             None => Err(ExitResult {
                 exit_code: ExitCode::from(2),
@@ -70,8 +195,66 @@ impl Cli {
             }),
         }
     }
-    pub fn subscribe_action<T: Action + 'static>(&mut self, caller: Command, action: T) -> &mut Self {
-        self.actions.insert(caller, Box::new(action));
+
+    async fn run(
+        &self,
+        command: &dyn Action<DB::Connection>,
+        counts: &mut RunCounts,
+    ) -> Result<ExitResult, ExitResult> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("can't connect: {}", e),
+        })?;
+        command.fetch(&mut *conn, counts).await?;
+        drop(conn);
+        let mut tx = self.pool.begin().await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("can't start transaction: {}", e),
+        })?;
+        // dropping the transaction on error rolls every write back
+        let result = command.execute(&mut *tx, counts).await?;
+        tx.commit().await.map_err(|e| ExitResult {
+            exit_code: ExitCode::from(2),
+            message: format!("commit failed: {}", e),
+        })?;
+        Ok(result)
+    }
+
+    // written on a connection of its own since the command transaction may have been rolled back.
+    // A run that can't be recorded, such as an `up` that failed before creating crawl_run, doesn't
+    // fail the command
+    async fn record(
+        &self,
+        command: &Command,
+        started_at: chrono::DateTime<chrono::Utc>,
+        counts: RunCounts,
+        result: &Result<ExitResult, ExitResult>,
+    ) {
+        let (status, message) = match result {
+            Ok(exit_result) => (RunStatus::Succeeded, exit_result.message.clone()),
+            Err(exit_result) => (RunStatus::Failed, exit_result.message.clone()),
+        };
+        let run = CrawlRun {
+            idrun: 0,
+            action: command.as_str().to_string(),
+            region: self.region.clone(),
+            started_at,
+            ended_at: chrono::Utc::now(),
+            status,
+            counts,
+            message,
+        };
+        let recorded = match self.pool.acquire().await {
+            Ok(mut conn) => self.run_service.create(&mut *conn, &run).await,
+            Err(err) => Err(err),
+        };
+        match recorded {
+            Ok(idrun) => info!("run {} recorded", idrun),
+            Err(err) => warn!("run not recorded: {}", err),
+        }
+    }
+    pub fn subscribe_action<T: Action<DB::Connection> + 'static>(&mut self, caller: Command, action: T) -> &mut Self {
+        self.actions.insert(std::mem::discriminant(&caller), Box::new(action));
         self
     }
 }
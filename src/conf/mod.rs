@@ -0,0 +1,36 @@
+use std::{borrow::Cow, env, fmt::Display, fs};
+
+use serde::de::DeserializeOwned;
+
+// The JSON dictionaries of the crawler (dietary rules, synonyms, school rules, campuses) are
+// shipped in conf/ and embedded in the binary. Each of them can be replaced at runtime by a file
+// whose path is given in its env variable
+
+#[derive(Debug)]
+pub enum ConfError {
+    Io(String),
+    Json(String),
+}
+
+impl Display for ConfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfError::Io(message) => write!(f, "can't read {}", message),
+            ConfError::Json(message) => write!(f, "invalid json: {}", message),
+        }
+    }
+}
+
+// the file at the path of env_var when it is set, the embedded default otherwise
+pub fn load(env_var: &str, default: &'static str) -> Result<Cow<'static, str>, ConfError> {
+    match env::var(env_var) {
+        Ok(path) => fs::read_to_string(&path)
+            .map(Cow::Owned)
+            .map_err(|e| ConfError::Io(format!("{} ({}): {}", path, env_var, e))),
+        Err(_) => Ok(Cow::Borrowed(default)),
+    }
+}
+
+pub fn parse<T: DeserializeOwned>(raw: &str) -> Result<T, ConfError> {
+    serde_json::from_str(raw).map_err(|e| ConfError::Json(e.to_string()))
+}
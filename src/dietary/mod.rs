@@ -0,0 +1,322 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::actions::meals::Foody,
+    conf::{self, ConfError},
+};
+
+const DEFAULT_DICTIONARY: &str = include_str!("../../conf/dietary.json");
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Diet {
+    Vegetarian,
+    Vegan,
+    HalalCompatible,
+    PorkFree,
+}
+
+// The 14 allergens that must be declared in the EU (regulation 1169/2011, annex II)
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Allergen {
+    Gluten,
+    Crustaceans,
+    Eggs,
+    Fish,
+    Peanuts,
+    Soybeans,
+    Milk,
+    Nuts,
+    Celery,
+    Mustard,
+    Sesame,
+    Sulphites,
+    Lupin,
+    Molluscs,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Dictionary {
+    pub version: u32,
+    pub rules: Vec<RuleDefinition>,
+    pub diets: Vec<DietDefinition>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RuleDefinition {
+    pub id: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub unless: Option<String>,
+    #[serde(default)]
+    pub contains: Vec<String>,
+    #[serde(default)]
+    pub clears: Vec<String>,
+    #[serde(default)]
+    pub allergens: Vec<Allergen>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DietDefinition {
+    pub id: String,
+    pub tag: Diet,
+    pub excludes: Vec<String>,
+    // components of which at least one must be found, a dish nothing is known about gets no diet
+    pub requires: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Tag<T> {
+    pub tag: T,
+    pub rules: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DishTags {
+    pub dish: String,
+    pub diets: Vec<Tag<Diet>>,
+    pub allergens: Vec<Tag<Allergen>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct MealTags {
+    pub dictionary_version: u32,
+    pub dishes: Vec<DishTags>,
+}
+
+#[derive(Debug)]
+pub enum DictionaryError {
+    Conf(ConfError),
+    Pattern(String),
+}
+
+impl From<ConfError> for DictionaryError {
+    fn from(error: ConfError) -> Self {
+        DictionaryError::Conf(error)
+    }
+}
+
+impl Display for DictionaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DictionaryError::Conf(error) => write!(f, "dietary dictionary: {}", error),
+            DictionaryError::Pattern(message) => write!(f, "invalid dietary rule: {}", message),
+        }
+    }
+}
+
+struct Rule {
+    id: String,
+    pattern: Regex,
+    unless: Option<Regex>,
+    contains: Vec<String>,
+    clears: Vec<String>,
+    allergens: Vec<Allergen>,
+}
+
+pub struct Classifier {
+    version: u32,
+    rules: Vec<Rule>,
+    diets: Vec<DietDefinition>,
+}
+
+impl Classifier {
+    pub fn load() -> Result<Self, DictionaryError> {
+        Self::from_json(&conf::load("DIETARY_RULES", DEFAULT_DICTIONARY)?)
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, DictionaryError> {
+        Self::new(conf::parse(raw)?)
+    }
+
+    pub fn new(dictionary: Dictionary) -> Result<Self, DictionaryError> {
+        let compile = |id: &str, pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| DictionaryError::Pattern(format!("{}: {}", id, e)))
+        };
+        let mut rules = Vec::new();
+        for rule in dictionary.rules {
+            rules.push(Rule {
+                pattern: compile(&rule.id, &rule.pattern)?,
+                unless: match rule.unless {
+                    Some(unless) => Some(compile(&rule.id, &unless)?),
+                    None => None,
+                },
+                id: rule.id,
+                contains: rule.contains,
+                clears: rule.clears,
+                allergens: rule.allergens,
+            });
+        }
+        Ok(Self {
+            version: dictionary.version,
+            rules,
+            diets: dictionary.diets,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn classify_dish(&self, dish: &str) -> DishTags {
+        let text = dish.to_lowercase();
+        let matched: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(&text))
+            .filter(|rule| !rule.unless.as_ref().is_some_and(|unless| unless.is_match(&text)))
+            .collect();
+
+        let cleared: BTreeSet<&str> = matched
+            .iter()
+            .flat_map(|rule| rule.clears.iter().map(String::as_str))
+            .collect();
+        let components: BTreeSet<&str> = matched
+            .iter()
+            .flat_map(|rule| rule.contains.iter().map(String::as_str))
+            .filter(|component| !cleared.contains(component))
+            .collect();
+
+        let mut diets = Vec::new();
+        for diet in self.diets.iter() {
+            if diet.excludes.iter().any(|excluded| components.contains(excluded.as_str()))
+                || !diet.requires.iter().any(|required| components.contains(required.as_str()))
+            {
+                continue;
+            }
+            // a diet tag is given by the diet definition itself, plus every rule that brought one
+            // of its required components and every marker that lifted one of its exclusions
+            // (e.g. "halal" on a chicken dish)
+            let mut rules = vec![diet.id.clone()];
+            for rule in matched.iter() {
+                let required = rule.contains.iter().any(|component| diet.requires.contains(component));
+                let lifted = rule.clears.iter().any(|cleared| diet.excludes.contains(cleared))
+                    && matched.iter().any(|other| {
+                        other.contains.iter().any(|component| rule.clears.contains(component))
+                    });
+                if required || lifted {
+                    rules.push(rule.id.clone());
+                }
+            }
+            diets.push(Tag { tag: diet.tag, rules });
+        }
+
+        let mut allergens: Vec<Tag<Allergen>> = Vec::new();
+        for rule in matched.iter() {
+            for allergen in rule.allergens.iter() {
+                match allergens.iter_mut().find(|tag| tag.tag == *allergen) {
+                    Some(tag) => tag.rules.push(rule.id.clone()),
+                    None => allergens.push(Tag {
+                        tag: *allergen,
+                        rules: vec![rule.id.clone()],
+                    }),
+                }
+            }
+        }
+        allergens.sort_by_key(|tag| tag.tag);
+
+        DishTags {
+            dish: dish.to_string(),
+            diets,
+            allergens,
+        }
+    }
+
+    pub fn classify(&self, foodies: &[Foody]) -> MealTags {
+        MealTags {
+            dictionary_version: self.version,
+            dishes: foodies
+                .iter()
+                .flat_map(|foody| foody.content.iter())
+                .map(|dish| self.classify_dish(dish))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diets(tags: &DishTags) -> Vec<Diet> {
+        tags.diets.iter().map(|tag| tag.tag).collect()
+    }
+
+    fn allergens(tags: &DishTags) -> Vec<Allergen> {
+        tags.allergens.iter().map(|tag| tag.tag).collect()
+    }
+
+    #[test]
+    fn test_default_dictionary_loads() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        assert!(classifier.version() >= 1);
+    }
+
+    #[test]
+    fn test_classify_pork() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        let tags = classifier.classify_dish("Sauté de porc au caramel");
+        assert!(diets(&tags).is_empty());
+    }
+
+    #[test]
+    fn test_classify_vegetarian_lasagnes() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        let tags = classifier.classify_dish("Lasagnes végétariennes");
+        assert_eq!(
+            diets(&tags),
+            vec![Diet::Vegetarian, Diet::PorkFree, Diet::HalalCompatible]
+        );
+        assert_eq!(allergens(&tags), vec![Allergen::Gluten, Allergen::Milk]);
+        let milk = tags.allergens.iter().find(|tag| tag.tag == Allergen::Milk).unwrap();
+        assert_eq!(milk.rules, vec!["dairy-milk".to_string()]);
+    }
+
+    #[test]
+    fn test_classify_halal_marker() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        let tags = classifier.classify_dish("Poulet halal rôti");
+        let halal = tags
+            .diets
+            .iter()
+            .find(|tag| tag.tag == Diet::HalalCompatible)
+            .unwrap();
+        assert_eq!(
+            halal.rules,
+            vec!["diet-halal-compatible".to_string(), "halal-marker".to_string()]
+        );
+        assert!(!diets(&tags).contains(&Diet::Vegetarian));
+    }
+
+    #[test]
+    fn test_classify_unknown_dish() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        for dish in ["Pizza margherita", "Hachis", "Pizza"] {
+            assert!(diets(&classifier.classify_dish(dish)).is_empty(), "{}", dish);
+        }
+        let tags = classifier.classify_dish("Ratatouille");
+        assert_eq!(
+            diets(&tags),
+            vec![Diet::Vegetarian, Diet::Vegan, Diet::PorkFree, Diet::HalalCompatible]
+        );
+        assert_eq!(
+            tags.diets[0].rules,
+            vec!["diet-vegetarian".to_string(), "vegetal-dish".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_classify_unless() {
+        let classifier = Classifier::from_json(DEFAULT_DICTIONARY).unwrap();
+        let tags = classifier.classify_dish("Riz au lait de coco");
+        assert!(!allergens(&tags).contains(&Allergen::Milk));
+        assert!(diets(&tags).contains(&Diet::Vegan));
+    }
+}
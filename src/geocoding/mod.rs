@@ -0,0 +1,331 @@
+use std::{collections::HashMap, env, fmt::Display, fs::File, io::Read};
+
+use regex::Regex;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{models::geo::Coordinates, text::fold};
+
+// Offline geocoder over the Base Adresse Nationale CSV exports (adresses-<department>.csv,
+// https://adresse.data.gouv.fr/donnees-nationales), used when a restaurant or a school comes
+// without coordinates
+
+const HOUSENUMBER_CONFIDENCE: f32 = 0.9;
+const STREET_CONFIDENCE: f32 = 0.6;
+// share of words two street names must have in common to be considered the same street
+const MIN_STREET_SIMILARITY: f32 = 0.75;
+// words left out of the similarity, "rue joseph anglada" is "rue du professeur joseph anglada"
+const PARTICLES: [&str; 7] = ["de", "du", "des", "la", "le", "les", "d"];
+
+// abbreviations found in CROUS and ONISEP addresses, BAN spells every street type out
+const ABBREVIATIONS: [(&str, &str); 16] = [
+    ("av", "avenue"),
+    ("ave", "avenue"),
+    ("bd", "boulevard"),
+    ("bld", "boulevard"),
+    ("boul", "boulevard"),
+    ("ch", "chemin"),
+    ("imp", "impasse"),
+    ("pl", "place"),
+    ("all", "allee"),
+    ("rte", "route"),
+    ("fbg", "faubourg"),
+    ("sq", "square"),
+    ("st", "saint"),
+    ("ste", "sainte"),
+    ("pr", "professeur"),
+    ("prof", "professeur"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeocodeSource {
+    // coordinates given by the CROUS restaurant page map
+    CrousMap,
+    // coordinates given by the ONISEP dataset
+    Onisep,
+    // BAN address with the same house number
+    BanHousenumber,
+    // BAN street, without the house number: centre of its known numbers
+    BanStreet,
+    // coordinates given by an imported file
+    Import,
+}
+
+impl GeocodeSource {
+    pub fn as_str(&self) -> &str {
+        match self {
+            GeocodeSource::CrousMap => "crous_map",
+            GeocodeSource::Onisep => "onisep",
+            GeocodeSource::BanHousenumber => "ban_housenumber",
+            GeocodeSource::BanStreet => "ban_street",
+            GeocodeSource::Import => "import",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geocoded {
+    pub coordinates: Coordinates,
+    pub source: GeocodeSource,
+    pub confidence: f32,
+}
+
+#[derive(Debug)]
+pub enum GeocoderError {
+    Io(String),
+    Csv(String),
+}
+
+impl Display for GeocoderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeocoderError::Io(message) => write!(f, "can't read BAN file: {}", message),
+            GeocoderError::Csv(message) => write!(f, "invalid BAN file: {}", message),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BanRow {
+    numero: String,
+    #[serde(default)]
+    rep: Option<String>,
+    nom_voie: String,
+    code_postal: String,
+    lon: f64,
+    lat: f64,
+}
+
+#[derive(Default)]
+struct Street {
+    words: Vec<String>,
+    numbers: HashMap<String, Coordinates>,
+}
+
+impl Street {
+    fn centroid(&self) -> Option<Coordinates> {
+        if self.numbers.is_empty() {
+            return None;
+        }
+        let count = self.numbers.len() as f64;
+        Some(Coordinates {
+            lat: self.numbers.values().map(|point| point.lat).sum::<f64>() / count,
+            lon: self.numbers.values().map(|point| point.lon).sum::<f64>() / count,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedAddress {
+    pub number: Option<String>,
+    pub street: String,
+    pub postcode: String,
+}
+
+pub struct Geocoder {
+    // streets by postcode, then by normalized name
+    streets: HashMap<String, HashMap<String, Street>>,
+    postcode: Regex,
+    number: Regex,
+}
+
+impl Geocoder {
+    // reads the files listed in BAN_CSV (comma separated), the geocoder is disabled without it
+    pub fn load() -> Result<Option<Self>, GeocoderError> {
+        let paths = match env::var("BAN_CSV") {
+            Ok(paths) => paths,
+            Err(_) => return Ok(None),
+        };
+        let mut geocoder = Self::new();
+        for path in paths.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+            let file = File::open(path).map_err(|e| GeocoderError::Io(format!("{}: {}", path, e)))?;
+            let count = geocoder.read(file)?;
+            info!("{}: {} BAN addresses loaded", path, count);
+        }
+        Ok(Some(geocoder))
+    }
+
+    pub fn new() -> Self {
+        Self {
+            streets: HashMap::new(),
+            postcode: Regex::new(r"\b(\d{5})\b").unwrap(),
+            number: Regex::new(r"^(\d+)\s*(bis|ter|quater|[a-d])?\b").unwrap(),
+        }
+    }
+
+    pub fn read<R: Read>(&mut self, reader: R) -> Result<usize, GeocoderError> {
+        let mut reader = csv::ReaderBuilder::new().delimiter(b';').from_reader(reader);
+        let mut count = 0;
+        for row in reader.deserialize::<BanRow>() {
+            let row = row.map_err(|e| GeocoderError::Csv(e.to_string()))?;
+            let name = normalize_street(&row.nom_voie);
+            let street = self
+                .streets
+                .entry(row.code_postal.trim().to_string())
+                .or_default()
+                .entry(name.clone())
+                .or_insert_with(|| Street {
+                    words: significant_words(&name).map(str::to_string).collect(),
+                    ..Default::default()
+                });
+            let number = format!("{}{}", row.numero.trim(), fold(row.rep.as_deref().unwrap_or("").trim()));
+            street.numbers.insert(number, Coordinates { lat: row.lat, lon: row.lon });
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // splits "Bâtiment 5, 1061 rue du Professeur Joseph Anglada 34090 Montpellier" into its house
+    // number, street and postcode. The postcode can also be given apart, as ONISEP does
+    pub fn parse(&self, address: &str, postcode: Option<&str>) -> Option<ParsedAddress> {
+        let found = self.postcode.captures(address);
+        let postcode = match (postcode, &found) {
+            (Some(postcode), _) => postcode.trim().to_string(),
+            (None, Some(captures)) => captures[1].to_string(),
+            (None, None) => return None,
+        };
+        let before_postcode = match found {
+            Some(captures) => &address[..captures.get(0).unwrap().start()],
+            None => address,
+        };
+        // the street is the last part holding a house number, or the last part otherwise
+        let parts: Vec<&str> = before_postcode
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect();
+        let part = parts
+            .iter()
+            .rev()
+            .find(|part| self.number.is_match(&fold(part)))
+            .or(parts.last())?;
+        let folded = fold(part);
+        let (number, street) = match self.number.captures(&folded) {
+            Some(captures) => (
+                Some(format!("{}{}", &captures[1], captures.get(2).map_or("", |rep| rep.as_str()))),
+                folded[captures.get(0).unwrap().end()..].to_string(),
+            ),
+            None => (None, folded),
+        };
+        let street = normalize_street(&street);
+        if street.is_empty() {
+            return None;
+        }
+        Some(ParsedAddress {
+            number,
+            street,
+            postcode,
+        })
+    }
+
+    pub fn geocode(&self, address: &str, postcode: Option<&str>) -> Option<Geocoded> {
+        let parsed = self.parse(address, postcode)?;
+        let streets = self.streets.get(&parsed.postcode)?;
+        let (street, similarity) = match streets.get(&parsed.street) {
+            Some(street) => (street, 1.0),
+            None => {
+                let words: Vec<&str> = significant_words(&parsed.street).collect();
+                streets
+                    .values()
+                    .map(|street| (street, similarity(&words, &street.words)))
+                    .filter(|(_, similarity)| *similarity >= MIN_STREET_SIMILARITY)
+                    .max_by(|a, b| a.1.total_cmp(&b.1))?
+            }
+        };
+        if let Some(coordinates) = parsed.number.as_ref().and_then(|number| street.numbers.get(number)) {
+            return Some(Geocoded {
+                coordinates: *coordinates,
+                source: GeocodeSource::BanHousenumber,
+                confidence: HOUSENUMBER_CONFIDENCE * similarity,
+            });
+        }
+        Some(Geocoded {
+            coordinates: street.centroid()?,
+            source: GeocodeSource::BanStreet,
+            confidence: STREET_CONFIDENCE * similarity,
+        })
+    }
+}
+
+// folded street name with its abbreviations spelled out and its punctuation dropped
+pub fn normalize_street(street: &str) -> String {
+    fold(street)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+                .map_or(word, |(_, full)| full)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn significant_words(street: &str) -> impl Iterator<Item = &str> {
+    street.split(' ').filter(|word| !PARTICLES.contains(word))
+}
+
+// share of the words of both names found in the other one
+fn similarity(words: &[&str], other: &[String]) -> f32 {
+    let common = words.iter().filter(|word| other.iter().any(|other| other == *word)).count();
+    let total = words.len() + other.len() - common;
+    if total == 0 {
+        return 0.0;
+    }
+    common as f32 / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BAN: &str = "id;id_fantoir;numero;rep;nom_voie;code_postal;code_insee;nom_commune;code_insee_ancienne_commune;nom_ancienne_commune;x;y;lon;lat;type_position;alias;nom_ld;libelle_acheminement;nom_afnor;source_position;source_nom_voie;certification_commune;cad_parcelles
+34172_5790_01061;;1061;;Rue du Professeur Joseph Anglada;34090;34172;Montpellier;;;;;3.8652;43.6323;entrée;;;MONTPELLIER;;commune;commune;1;
+34172_5790_01063;;1063;;Rue du Professeur Joseph Anglada;34090;34172;Montpellier;;;;;3.8660;43.6331;entrée;;;MONTPELLIER;;commune;commune;1;
+34172_0140_00002;;2;bis;Place Eugène Bataillon;34095;34172;Montpellier;;;;;3.8640;43.6318;entrée;;;MONTPELLIER;;commune;commune;1;
+";
+
+    fn geocoder() -> Geocoder {
+        let mut geocoder = Geocoder::new();
+        assert_eq!(geocoder.read(BAN.as_bytes()).unwrap(), 3);
+        geocoder
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let geocoder = geocoder();
+        assert_eq!(
+            geocoder.parse("Bâtiment 5, 1061 rue du Pr. Joseph Anglada 34090 Montpellier", None),
+            Some(ParsedAddress {
+                number: Some("1061".to_string()),
+                street: "rue du professeur joseph anglada".to_string(),
+                postcode: "34090".to_string(),
+            })
+        );
+        assert_eq!(
+            geocoder.parse("2 bis pl. Eugène Bataillon", Some("34095")).unwrap().number,
+            Some("2bis".to_string())
+        );
+        assert_eq!(geocoder.parse("Montpellier", None), None);
+    }
+
+    #[test]
+    fn test_geocode() {
+        let geocoder = geocoder();
+        let housenumber = geocoder
+            .geocode("1061 rue du Professeur Joseph Anglada 34090 Montpellier", None)
+            .unwrap();
+        assert_eq!(housenumber.source, GeocodeSource::BanHousenumber);
+        assert_eq!(housenumber.coordinates, Coordinates { lat: 43.6323, lon: 3.8652 });
+        assert_eq!(housenumber.confidence, HOUSENUMBER_CONFIDENCE);
+
+        let street = geocoder
+            .geocode("rue Joseph Anglada", Some("34090"))
+            .unwrap_or_else(|| panic!("street not found"));
+        assert_eq!(street.source, GeocodeSource::BanStreet);
+        assert!(street.confidence < STREET_CONFIDENCE);
+
+        assert!(geocoder.geocode("1061 rue du Professeur Joseph Anglada", Some("34000")).is_none());
+    }
+}
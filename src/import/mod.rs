@@ -0,0 +1,399 @@
+use std::{collections::HashMap, collections::HashSet, fmt::Display, fs, path::Path};
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::models::{
+    geo::Coordinates,
+    restaurants::{Restaurant, SOURCE_IMPORT},
+    schools::School,
+};
+
+// Reads schools and restaurants missing from the open datasets out of local files, either CSV
+// (`,` or `;` separated, with a header) or a GeoJSON FeatureCollection of points. Columns, or
+// feature properties, are matched by name:
+//
+// schools: uai*, name*, long_name, address, postcode, commune, phone, type, website, lat, lon
+// restaurants: url*, name*, address, hours, lat, lon
+//
+// (* required). GeoJSON features take their coordinates from their geometry instead of lat/lon.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    GeoJson,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self, ImportError> {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .as_deref()
+        {
+            Some("csv") => Ok(Format::Csv),
+            Some("geojson") | Some("json") => Ok(Format::GeoJson),
+            _ => Err(ImportError::Format(format!(
+                "{}: expected a .csv, .geojson or .json file",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(String),
+    Format(String),
+    // every invalid row, nothing is imported when there is one
+    Invalid(Vec<String>),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(message) => write!(f, "can't read import file: {}", message),
+            ImportError::Format(message) => write!(f, "invalid import file: {}", message),
+            ImportError::Invalid(errors) => {
+                write!(f, "{} invalid rows: {}", errors.len(), errors.join("; "))
+            }
+        }
+    }
+}
+
+// a CSV row or a GeoJSON feature, its fields keyed by lowercase column name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    // line of the CSV file, or position of the feature starting at 1
+    pub line: usize,
+    pub fields: HashMap<String, String>,
+    pub geometry: Option<Coordinates>,
+}
+
+impl Record {
+    fn get(&self, column: &str) -> Option<String> {
+        self.fields
+            .get(column)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+    }
+
+    fn required(&self, column: &str, errors: &mut Vec<String>) -> Option<String> {
+        let value = self.get(column);
+        if value.is_none() {
+            errors.push(format!("line {}: missing {}", self.line, column));
+        }
+        value
+    }
+
+    // the feature geometry, else the lat and lon columns
+    fn coordinates(&self, errors: &mut Vec<String>) -> Option<Coordinates> {
+        if self.geometry.is_some() {
+            return self.geometry;
+        }
+        match (self.get("lat"), self.get("lon")) {
+            (None, None) => None,
+            (Some(lat), Some(lon)) => match format!("{},{}", lat, lon).parse() {
+                Ok(coordinates) => Some(coordinates),
+                Err(e) => {
+                    errors.push(format!("line {}: {}", self.line, e));
+                    None
+                }
+            },
+            _ => {
+                errors.push(format!("line {}: lat and lon go together", self.line));
+                None
+            }
+        }
+    }
+}
+
+// an imported row, with the coordinates the file gives
+#[derive(Debug, Clone)]
+pub struct Imported<T> {
+    pub line: usize,
+    pub item: T,
+    pub coordinates: Option<Coordinates>,
+}
+
+pub fn read(path: &Path) -> Result<Vec<Record>, ImportError> {
+    let format = Format::from_path(path)?;
+    let raw = fs::read_to_string(path)
+        .map_err(|e| ImportError::Io(format!("{}: {}", path.display(), e)))?;
+    match format {
+        Format::Csv => read_csv(&raw),
+        Format::GeoJson => read_geojson(&raw),
+    }
+}
+
+pub fn read_csv(raw: &str) -> Result<Vec<Record>, ImportError> {
+    let header = raw.lines().next().unwrap_or_default();
+    let delimiter = if header.contains(';') && !header.contains(',') {
+        b';'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(raw.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| ImportError::Format(e.to_string()))?
+        .iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(|e| ImportError::Format(e.to_string()))?;
+        records.push(Record {
+            line: row.position().map_or(0, |position| position.line() as usize),
+            fields: headers
+                .iter()
+                .cloned()
+                .zip(row.iter().map(|value| value.to_string()))
+                .collect(),
+            geometry: None,
+        });
+    }
+    Ok(records)
+}
+
+pub fn read_geojson(raw: &str) -> Result<Vec<Record>, ImportError> {
+    let collection: Value =
+        serde_json::from_str(raw).map_err(|e| ImportError::Format(e.to_string()))?;
+    let features = collection
+        .get("features")
+        .and_then(|features| features.as_array())
+        .ok_or(ImportError::Format("expected a FeatureCollection".to_string()))?;
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let line = index + 1;
+        let fields = feature
+            .get("properties")
+            .and_then(|properties| properties.as_object())
+            .map(|properties| {
+                properties
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let value = match value {
+                            Value::String(text) => text.clone(),
+                            Value::Number(number) => number.to_string(),
+                            Value::Bool(flag) => flag.to_string(),
+                            _ => return None,
+                        };
+                        Some((key.trim().to_lowercase(), value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let geometry = match feature.get("geometry") {
+            None | Some(Value::Null) => None,
+            Some(geometry) => {
+                let position = geometry
+                    .get("coordinates")
+                    .and_then(|coordinates| coordinates.as_array())
+                    .filter(|_| geometry.get("type").and_then(|kind| kind.as_str()) == Some("Point"));
+                // GeoJSON positions are [lon, lat]
+                match position.map(|position| {
+                    format!(
+                        "{},{}",
+                        position.get(1).unwrap_or(&Value::Null),
+                        position.first().unwrap_or(&Value::Null)
+                    )
+                    .parse::<Coordinates>()
+                }) {
+                    Some(Ok(coordinates)) => Some(coordinates),
+                    _ => {
+                        errors.push(format!("feature {}: expected a Point geometry", line));
+                        None
+                    }
+                }
+            }
+        };
+        records.push(Record {
+            line,
+            fields,
+            geometry,
+        });
+    }
+    if !errors.is_empty() {
+        return Err(ImportError::Invalid(errors));
+    }
+    Ok(records)
+}
+
+pub fn schools(records: &[Record]) -> Result<Vec<Imported<School>>, ImportError> {
+    let uai = Regex::new(r"^[0-9]{7}[A-Z]$").unwrap();
+    let postcode = Regex::new(r"^[0-9]{5}$").unwrap();
+    let mut errors = Vec::new();
+    let mut codes = HashSet::new();
+    let mut schools = Vec::new();
+    for record in records {
+        let line = record.line;
+        let code_uai = record
+            .required("uai", &mut errors)
+            .map(|code| code.to_uppercase());
+        let name = record.required("name", &mut errors);
+        let coordinates = record.coordinates(&mut errors);
+        let school_postcode = record.get("postcode");
+        let website = record.get("website");
+        if let Some(code) = &code_uai {
+            if !uai.is_match(code) {
+                errors.push(format!("line {}: invalid UAI code {}", line, code));
+            } else if !codes.insert(code.clone()) {
+                errors.push(format!("line {}: UAI code {} is imported twice", line, code));
+            }
+        }
+        if let Some(school_postcode) = school_postcode.as_ref().filter(|cp| !postcode.is_match(cp)) {
+            errors.push(format!("line {}: invalid postcode {}", line, school_postcode));
+        }
+        if let Some(website) = website.as_ref().filter(|url| !is_url(url)) {
+            errors.push(format!("line {}: invalid website {}", line, website));
+        }
+        if let (Some(code_uai), Some(name)) = (code_uai, name) {
+            schools.push(Imported {
+                line,
+                item: School {
+                    idschool: 0,
+                    code_uai,
+                    long_name: record.get("long_name").unwrap_or(name.clone()),
+                    name,
+                    coords: None,
+                    address: record.get("address"),
+                    postcode: school_postcode,
+                    commune: record.get("commune"),
+                    phone: record.get("phone"),
+                    school_type: record.get("type"),
+                    website,
+                    geocode_source: None,
+                    geocode_confidence: None,
+                },
+                coordinates,
+            });
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ImportError::Invalid(errors));
+    }
+    Ok(schools)
+}
+
+pub fn restaurants(records: &[Record]) -> Result<Vec<Imported<Restaurant>>, ImportError> {
+    let mut errors = Vec::new();
+    let mut urls = HashSet::new();
+    let mut restaurants = Vec::new();
+    for record in records {
+        let line = record.line;
+        let url = record.required("url", &mut errors);
+        let name = record.required("name", &mut errors);
+        let coordinates = record.coordinates(&mut errors);
+        if let Some(url) = &url {
+            if !is_url(url) {
+                errors.push(format!("line {}: invalid url {}", line, url));
+            } else if !urls.insert(url.clone()) {
+                errors.push(format!("line {}: url {} is imported twice", line, url));
+            }
+        }
+        if let (Some(url), Some(name)) = (url, name) {
+            restaurants.push(Imported {
+                line,
+                item: Restaurant {
+                    idrestaurant: None,
+                    url,
+                    name,
+                    gpscoord: None,
+                    hours: record.get("hours"),
+                    address: record.get("address"),
+                    geocode_source: None,
+                    geocode_confidence: None,
+                    source: SOURCE_IMPORT.to_string(),
+                },
+                coordinates,
+            });
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ImportError::Invalid(errors));
+    }
+    Ok(restaurants)
+}
+
+fn is_url(text: &str) -> bool {
+    url::Url::parse(text).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schools_csv() {
+        let records = read_csv(
+            "UAI;Name;Postcode;Lat;Lon;Website\n\
+            0342321K;IUT Béziers;34500;43.3442;3.2310;https://iut-beziers.edu.umontpellier.fr\n\
+            0342322l;Institut Privé;34000;;;\n",
+        )
+        .unwrap();
+        let imported = schools(&records).unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].item.long_name, "IUT Béziers");
+        assert_eq!(
+            imported[0].coordinates,
+            Some(Coordinates { lat: 43.3442, lon: 3.2310 })
+        );
+        assert_eq!(imported[1].item.code_uai, "0342322L");
+        assert_eq!(imported[1].coordinates, None);
+
+        let records = read_csv(
+            "uai,name,postcode,lat,lon\n\
+            034232,IUT,34500,43.3,\n\
+            0342321K,,3450,,\n\
+            0342321K,IUT,34500,95,3.2\n",
+        )
+        .unwrap();
+        match schools(&records) {
+            Err(ImportError::Invalid(errors)) => assert_eq!(
+                errors,
+                vec![
+                    "line 2: lat and lon go together",
+                    "line 2: invalid UAI code 034232",
+                    "line 3: missing name",
+                    "line 3: invalid postcode 3450",
+                    "line 4: invalid coordinates \"95,3.2\", expected lat,lon",
+                    "line 4: UAI code 0342321K is imported twice",
+                ]
+            ),
+            _ => panic!("expected invalid rows"),
+        }
+    }
+
+    #[test]
+    fn test_restaurants_geojson() {
+        let records = read_geojson(
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [3.8772, 43.6109]},
+                 "properties": {"url": "https://cafet.example.fr", "name": "Cafétéria partenaire", "hours": "11h30-14h"}},
+                {"type": "Feature", "geometry": null,
+                 "properties": {"url": "ftp://cafet.example.fr", "name": "Sans page"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            records[0].geometry,
+            Some(Coordinates { lat: 43.6109, lon: 3.8772 })
+        );
+        match restaurants(&records) {
+            Err(ImportError::Invalid(errors)) => {
+                assert_eq!(errors, vec!["line 2: invalid url ftp://cafet.example.fr"])
+            }
+            _ => panic!("expected invalid rows"),
+        }
+        let imported = restaurants(&records[..1]).unwrap();
+        assert_eq!(imported[0].item.name, "Cafétéria partenaire");
+        assert_eq!(imported[0].item.hours.as_deref(), Some("11h30-14h"));
+    }
+}
@@ -1,6 +1,13 @@
 mod cli;
+mod conf;
+mod dietary;
+mod geocoding;
+mod import;
 mod models;
+mod opendatasoft;
+mod selection;
 mod telemetry;
+mod text;
 
 use clap::Parser;
 use telemetry::log::init_logger;
@@ -8,9 +15,10 @@ use std::{env, process::ExitCode, sync::Arc};
 
 use cli::{
     actions::{
-        bootstrap::BootstrapAction, meals::MealsAction, ping::PingAction, restaurants::RestaurantAction, schools::SchoolAction, up::UpAction
-    }, Action, App, Cli, Command, ExitResult
+        bootstrap::BootstrapAction, find_dish::FindDishAction, import::{ImportRestaurantsAction, ImportSchoolsAction}, keywords::KeywordsGcAction, link::LinkAction, meals::MealsAction, ping::PingAction, restaurants::RestaurantAction, runs::RunsAction, schools::{SchoolAction, SchoolSource}, search::SearchAction, suggest::SuggestAction, up::UpAction
+    }, Action, App, Cli, Command, ExitResult, ImportCommand, KeywordsCommand
 };
+use models::crawl_run::{CrawlRunService, RunCounts, DEFAULT_REGION};
 use dotenv::dotenv;
 use tracing::{error, info, span, Level};
 
@@ -30,7 +38,9 @@ async fn main() -> ExitCode {
     let now = chrono::Utc::now();
 
     if args.ping {
-        match PingAction::new("https://www.crous-montpellier.fr/se-restaurer/ou-manger/").execute().await {
+        match PingAction::new("https://www.crous-montpellier.fr/se-restaurer/ou-manger/")
+            .execute(&mut (), &mut RunCounts::default())
+            .await {
             Ok(res) => {
                 info!("{}", res.message);
             }
@@ -41,12 +51,28 @@ async fn main() -> ExitCode {
         }
     }
 
-    let pg_database = get_env_variable("DATABASE_URL");
-    let pool = match pg_database {
+    // a dry run writes nothing, it runs on in-memory schools without connecting to the database
+    if let Command::Schools { dry_run: true } = &args.action {
+        let school_source = match SchoolSource::from_env() {
+            Ok(source) => source,
+            Err(err) => {
+                error!("{}", err);
+                return ExitCode::from(2);
+            }
+        };
+        let action = SchoolAction::new(
+            Arc::new(models::memory::MemorySchools::new()),
+            None,
+            school_source,
+            true,
+        );
+        return exit(&action.execute(&mut (), &mut RunCounts::default()).await, now);
+    }
+
+    let database = match get_env_variable("DATABASE_URL") {
         Ok(database) => {
             info!(database);
-            let pool = sqlx::PgPool::connect(&database).await.unwrap();
-            Arc::new(pool)
+            database
         }
         Err(err) => {
             error!("{}", err.message);
@@ -54,42 +80,325 @@ async fn main() -> ExitCode {
         }
     };
 
-    let restaurant_service = Arc::new(models::restaurants::RestaurantService::new(pool.clone()));
-    let keyword_service = Arc::new(models::keywords::KeywordService::new(pool.clone()));
-    let meal_service = Arc::new(models::meals::MealService::new(pool.clone()));
+    let synonyms = match text::synonyms::Synonyms::load() {
+        Ok(synonyms) => {
+            info!("synonyms v{} loaded", synonyms.version());
+            Arc::new(synonyms)
+        }
+        Err(err) => {
+            error!("synonyms: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let region = env::var("CRAWL_REGION").unwrap_or(DEFAULT_REGION.to_string());
 
-    let school_service = Arc::new(models::schools::SchoolService::new(pool.clone()));
+    if database.starts_with("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return run_sqlite(&database, args, synonyms, region, now).await;
+        #[cfg(not(feature = "sqlite"))]
+        {
+            error!("{} needs the crawler to be built with the sqlite feature", database);
+            return ExitCode::from(2);
+        }
+    }
+
+    let pool = Arc::new(sqlx::PgPool::connect(&database).await.unwrap());
+
+    let restaurant_service = Arc::new(models::restaurants::RestaurantService::new());
 
-    let school_action = SchoolAction::new(school_service.clone());
+    let keyword_service = Arc::new(models::keywords::KeywordService::new(synonyms.clone()));
+    let meal_service = Arc::new(models::meals::MealService::new(synonyms.clone()));
+    let menu_status_service = Arc::new(models::menu_status::MenuStatusService::new());
+    let search_service = Arc::new(models::search::SearchService::new(synonyms.clone()));
 
-    let restaurant_action =
-        RestaurantAction::new(restaurant_service.clone(), keyword_service.clone());
+    let school_service = Arc::new(models::schools::SchoolService::new());
+    let run_service = Arc::new(CrawlRunService::new());
+
+    let link_settings = match models::restaurant_school::LinkSettings::from_env() {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+    let restaurant_school_service = Arc::new(
+        models::restaurant_school::RestaurantSchoolService::new(link_settings),
+    );
+
+    let campus_mapping = match models::campus::CampusMapping::load() {
+        Ok(mapping) => {
+            info!("campuses v{} loaded", mapping.version);
+            mapping
+        }
+        Err(err) => {
+            error!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+    let campus_service = Arc::new(models::campus::CampusService::new(
+        campus_mapping,
+        link_settings,
+    ));
+
+    let school_source = match SchoolSource::from_env() {
+        Ok(source) => source,
+        Err(err) => {
+            error!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+    let link_action = Arc::new(LinkAction::new(
+        restaurant_school_service.clone(),
+        campus_service.clone(),
+    ));
+
+    let restaurant_action = RestaurantAction::new(
+        restaurant_service.clone(),
+        keyword_service.clone(),
+        search_service.clone(),
+        Some(link_action.clone()),
+    );
     let meal_action = MealsAction::new(
         meal_service.clone(),
         restaurant_service.clone(),
         keyword_service.clone(),
+        menu_status_service.clone(),
+        search_service.clone(),
     );
 
     let bootstrap_action = BootstrapAction::new(
-        pool.clone(),
         meal_service.clone(),
         restaurant_service.clone(),
         keyword_service.clone(),
+        menu_status_service.clone(),
+        search_service.clone(),
+        link_action.clone(),
     );
 
-    let result = &Cli::new()
+    if matches!(
+        args.action,
+        Command::Restaurants | Command::Meals | Command::Keywords { .. } | Command::Import { .. }
+    ) {
+        // checked outside of the command transaction, missing categories are created for good
+        let checked = match pool.acquire().await {
+            Ok(mut conn) => keyword_service.categories(&mut conn).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = checked {
+            error!("keyword categories check failed: {}", err);
+            return ExitCode::from(2);
+        }
+    }
+
+    let mut cli = Cli::new(pool.clone(), run_service.clone(), region);
+    if let Command::Runs { command } = &args.action {
+        cli.subscribe_action(
+            args.action.clone(),
+            RunsAction::new(run_service.clone(), command.clone()),
+        );
+    }
+    if let Command::Search { query, limit } = &args.action {
+        cli.subscribe_action(
+            args.action.clone(),
+            SearchAction::new(search_service.clone(), query.clone(), *limit),
+        );
+    }
+    if let Command::Suggest { prefix, limit } = &args.action {
+        cli.subscribe_action(
+            args.action.clone(),
+            SuggestAction::new(keyword_service.clone(), prefix.clone(), *limit),
+        );
+    }
+    if let Command::Schools { dry_run } = &args.action {
+        cli.subscribe_action(
+            args.action.clone(),
+            SchoolAction::new(
+                school_service.clone(),
+                Some(link_action.clone()),
+                school_source,
+                *dry_run,
+            ),
+        );
+    }
+    if let Command::FindDish { query, date, near } = &args.action {
+        cli.subscribe_action(
+            args.action.clone(),
+            FindDishAction::new(
+                meal_service.clone(),
+                query.clone(),
+                date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+                *near,
+            ),
+        );
+    }
+    if let Command::Keywords {
+        command: KeywordsCommand::Gc { older_than },
+    } = &args.action
+    {
+        cli.subscribe_action(
+            args.action.clone(),
+            KeywordsGcAction::new(
+                keyword_service.clone(),
+                restaurant_service.clone(),
+                meal_service.clone(),
+                *older_than,
+            ),
+        );
+    }
+    if let Command::Import { command } = &args.action {
+        match command {
+            ImportCommand::Schools { file } => cli.subscribe_action(
+                args.action.clone(),
+                ImportSchoolsAction::new(school_service.clone(), link_action.clone(), file.clone()),
+            ),
+            ImportCommand::Restaurants { file } => cli.subscribe_action(
+                args.action.clone(),
+                ImportRestaurantsAction::new(
+                    restaurant_service.clone(),
+                    keyword_service.clone(),
+                    search_service.clone(),
+                    link_action.clone(),
+                    file.clone(),
+                ),
+            ),
+        };
+    }
+
+    let result = &cli
         .subscribe_action(Command::Restaurants, restaurant_action)
-        .subscribe_action(Command::Up, UpAction { pool: pool.clone() })
+        .subscribe_action(Command::Up, UpAction {})
         .subscribe_action(Command::Meals, meal_action)
         .subscribe_action(Command::Bootstrap, bootstrap_action)
-        .subscribe_action(Command::Schools, school_action)
+        .subscribe_action(
+            Command::Link,
+            LinkAction::new(restaurant_school_service.clone(), campus_service.clone()),
+        )
         .execute(args)
         .await;
 
+    exit(result, now)
+}
+
+// the commands only relying on repositories, the others need Postgres (full text search, points)
+#[cfg(feature = "sqlite")]
+async fn run_sqlite(
+    database: &str,
+    args: App,
+    synonyms: Arc<text::synonyms::Synonyms>,
+    region: String,
+    started_at: chrono::DateTime<chrono::Utc>,
+) -> ExitCode {
+    if matches!(
+        args.action,
+        Command::Bootstrap | Command::Link | Command::Search { .. } | Command::Import { .. }
+    ) {
+        error!("{} needs a Postgres database", args.action.as_str());
+        return ExitCode::from(2);
+    }
+
+    let pool = match sqlx::SqlitePool::connect(database).await {
+        Ok(pool) => Arc::new(pool),
+        Err(err) => {
+            error!("can't open {}: {}", database, err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let restaurant_service = Arc::new(models::restaurants::RestaurantService::new());
+    let keyword_service = Arc::new(models::keywords::KeywordService::new(synonyms.clone()));
+    let meal_service = Arc::new(models::meals::MealService::new(synonyms.clone()));
+    let menu_status_service = Arc::new(models::menu_status::MenuStatusService::new());
+    let search_service = Arc::new(models::search::SearchService::new(synonyms));
+    let school_service = Arc::new(models::schools::SchoolService::new());
+    let run_service = Arc::new(CrawlRunService::new());
+
+    let mut cli = Cli::<sqlx::Sqlite>::new(pool, run_service.clone(), region);
+    match &args.action {
+        Command::Runs { command } => {
+            cli.subscribe_action(
+                args.action.clone(),
+                RunsAction::new(run_service, command.clone()),
+            );
+        }
+        Command::Schools { dry_run } => {
+            let school_source = match SchoolSource::from_env() {
+                Ok(source) => source,
+                Err(err) => {
+                    error!("{}", err);
+                    return ExitCode::from(2);
+                }
+            };
+            cli.subscribe_action(
+                args.action.clone(),
+                SchoolAction::new(school_service, None, school_source, *dry_run),
+            );
+        }
+        Command::Suggest { prefix, limit } => {
+            cli.subscribe_action(
+                args.action.clone(),
+                SuggestAction::new(keyword_service.clone(), prefix.clone(), *limit),
+            );
+        }
+        Command::FindDish { query, date, near } => {
+            cli.subscribe_action(
+                args.action.clone(),
+                FindDishAction::new(
+                    meal_service.clone(),
+                    query.clone(),
+                    date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+                    *near,
+                ),
+            );
+        }
+        Command::Keywords {
+            command: KeywordsCommand::Gc { older_than },
+        } => {
+            cli.subscribe_action(
+                args.action.clone(),
+                KeywordsGcAction::new(
+                    keyword_service.clone(),
+                    restaurant_service.clone(),
+                    meal_service.clone(),
+                    *older_than,
+                ),
+            );
+        }
+        _ => {}
+    }
+
+    let result = &cli
+        .subscribe_action(
+            Command::Restaurants,
+            RestaurantAction::new(
+                restaurant_service.clone(),
+                keyword_service.clone(),
+                search_service.clone(),
+                None,
+            ),
+        )
+        .subscribe_action(Command::Up, UpAction {})
+        .subscribe_action(
+            Command::Meals,
+            MealsAction::new(
+                meal_service,
+                restaurant_service,
+                keyword_service,
+                menu_status_service,
+                search_service,
+            ),
+        )
+        .execute(args)
+        .await;
+
+    exit(result, started_at)
+}
+
+fn exit(result: &Result<ExitResult, ExitResult>, started_at: chrono::DateTime<chrono::Utc>) -> ExitCode {
     match result {
         Ok(exit_result) => {
             info!("{}", exit_result.message);
-            info!("took: {}", chrono::Utc::now().signed_duration_since(now));
+            info!("took: {}", chrono::Utc::now().signed_duration_since(started_at));
             exit_result.exit_code
         }
         Err(exit_result) => {
@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use sqlx::PgConnection;
+use tracing::warn;
+
+use crate::{
+    conf,
+    models::{
+        geo::Coordinates,
+        restaurant_school::{compute_links, located, LinkSettings},
+    },
+};
+
+const DEFAULT_MAPPING: &str = include_str!("../../conf/campuses.json");
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CuratedCampus {
+    pub name: String,
+    pub center: Coordinates,
+    // schools within this distance of the center belong to the campus
+    pub radius_km: f64,
+    // schools that belong to the campus wherever they are
+    #[serde(default)]
+    pub uai: Vec<String>,
+}
+
+// curated campuses, the remaining schools are clustered when they are within cluster_km of
+// each other, and a cluster of fewer than min_schools is no campus
+#[derive(Deserialize, Debug, Clone)]
+pub struct CampusMapping {
+    pub version: u32,
+    pub cluster_km: f64,
+    #[serde(default = "default_min_schools")]
+    pub min_schools: usize,
+    pub campuses: Vec<CuratedCampus>,
+}
+
+fn default_min_schools() -> usize {
+    2
+}
+
+impl CampusMapping {
+    pub fn load() -> Result<Self, String> {
+        Self::from_json(&conf::load("CAMPUSES", DEFAULT_MAPPING).map_err(|e| format!("campuses: {}", e))?)
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, String> {
+        let mut mapping: CampusMapping = conf::parse(raw).map_err(|e| format!("campuses: {}", e))?;
+        if mapping.cluster_km < 0.0 {
+            return Err(format!("invalid campuses: negative cluster_km {}", mapping.cluster_km));
+        }
+        if mapping.min_schools == 0 {
+            return Err("invalid campuses: min_schools must be at least 1".to_string());
+        }
+        let mut names = HashSet::new();
+        for campus in mapping.campuses.iter_mut() {
+            if !names.insert(campus.name.clone()) {
+                return Err(format!("invalid campuses: {} is defined twice", campus.name));
+            }
+            campus.uai = campus.uai.iter().map(|code| code.trim().to_uppercase()).collect();
+        }
+        Ok(mapping)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CampusSource {
+    Curated,
+    Cluster,
+}
+
+impl CampusSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CampusSource::Curated => "curated",
+            CampusSource::Cluster => "cluster",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CampusSchool {
+    pub idschool: i32,
+    pub code_uai: Option<String>,
+    pub name: String,
+    pub coordinates: Coordinates,
+}
+
+// a campus is kept, with its id and links, as long as its key is: the name of a curated campus,
+// the UAI of a school of a cluster
+#[derive(Debug, Clone, PartialEq)]
+pub struct CampusGroup {
+    pub key: String,
+    pub name: String,
+    pub coordinates: Coordinates,
+    pub source: CampusSource,
+    pub schools: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestaurantCampus {
+    pub idcampus: i32,
+    pub idrestaurant: i32,
+    pub distance_km: f64,
+}
+
+pub struct CampusLinks {
+    pub campuses: Vec<(i32, CampusGroup)>,
+    pub links: Vec<RestaurantCampus>,
+}
+
+#[derive(Clone)]
+pub struct CampusService {
+    pub mapping: CampusMapping,
+    pub settings: LinkSettings,
+}
+
+impl CampusService {
+    pub fn new(mapping: CampusMapping, settings: LinkSettings) -> Self {
+        Self {
+            mapping,
+            settings,
+        }
+    }
+
+    // groups the active schools into campuses and links each campus to its restaurants, the
+    // same way schools are linked
+    pub async fn rebuild(&self, conn: &mut PgConnection) -> Result<CampusLinks, sqlx::Error> {
+        let schools = sqlx::query_as::<_, (i32, Option<String>, String, String)>(
+            "SELECT idschool, code_uai, name, coords::text FROM school WHERE coords IS NOT NULL AND active",
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .filter_map(|(idschool, code_uai, name, point)| match Coordinates::from_point(&point) {
+            Some(coordinates) => Some(CampusSchool {
+                idschool,
+                code_uai,
+                name,
+                coordinates,
+            }),
+            None => {
+                warn!("invalid point {} for {}", point, idschool);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+        let restaurants = located(
+            &mut *conn,
+            "SELECT idrestaurant, gpscoord::text FROM restaurant WHERE gpscoord IS NOT NULL",
+        )
+        .await?;
+        let known = sqlx::query_scalar::<_, String>("SELECT key FROM campus WHERE source = $1")
+            .bind(CampusSource::Cluster.as_str())
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let groups = group_campuses(&schools, &self.mapping, &known);
+        let mut campuses = Vec::new();
+        for group in groups {
+            let idcampus = sqlx::query_scalar::<_, i32>(
+                r#"INSERT INTO campus(key, name, coords, source) VALUES ($1, $2, $3::point, $4)
+                ON CONFLICT (source, key) DO UPDATE SET name = EXCLUDED.name, coords = EXCLUDED.coords, updated_at = now()
+                RETURNING idcampus"#,
+            )
+            .bind(&group.key)
+            .bind(&group.name)
+            .bind(group.coordinates.to_point())
+            .bind(group.source.as_str())
+            .fetch_one(&mut *conn)
+            .await?;
+            campuses.push((idcampus, group));
+        }
+        sqlx::query("DELETE FROM campus WHERE NOT idcampus = ANY($1)")
+            .bind(campuses.iter().map(|(idcampus, _)| *idcampus).collect::<Vec<_>>())
+            .execute(&mut *conn)
+            .await?;
+
+        let members: Vec<(i32, i32)> = campuses
+            .iter()
+            .flat_map(|(idcampus, group)| group.schools.iter().map(|idschool| (*idschool, *idcampus)))
+            .collect();
+        sqlx::query("UPDATE school SET idcampus = NULL WHERE idcampus IS NOT NULL")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            r#"UPDATE school SET idcampus = member.idcampus
+            FROM UNNEST($1::int[], $2::int[]) AS member(idschool, idcampus)
+            WHERE school.idschool = member.idschool"#,
+        )
+        .bind(members.iter().map(|(idschool, _)| *idschool).collect::<Vec<_>>())
+        .bind(members.iter().map(|(_, idcampus)| *idcampus).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+
+        let centers: Vec<(i32, Coordinates)> = campuses
+            .iter()
+            .map(|(idcampus, group)| (*idcampus, group.coordinates))
+            .collect();
+        let links: Vec<RestaurantCampus> = compute_links(&centers, &restaurants, &self.settings)
+            .into_iter()
+            .map(|link| RestaurantCampus {
+                idcampus: link.idschool,
+                idrestaurant: link.idrestaurant,
+                distance_km: link.distance_km,
+            })
+            .collect();
+        sqlx::query("DELETE FROM restaurant_campus")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            r#"INSERT INTO restaurant_campus(idcampus, idrestaurant, distance_km)
+            SELECT * FROM UNNEST($1::int[], $2::int[], $3::float8[])"#,
+        )
+        .bind(links.iter().map(|link| link.idcampus).collect::<Vec<_>>())
+        .bind(links.iter().map(|link| link.idrestaurant).collect::<Vec<_>>())
+        .bind(links.iter().map(|link| link.distance_km).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+        Ok(CampusLinks { campuses, links })
+    }
+}
+
+// a school belongs to the curated campus listing its UAI, else to the nearest curated campus
+// within its radius. The others are grouped with every school less than cluster_km away. A
+// group keeps the key it had among the known ones, else takes the lowest UAI of its schools,
+// and is named after the school of its key
+pub fn group_campuses(
+    schools: &[CampusSchool],
+    mapping: &CampusMapping,
+    known: &HashSet<String>,
+) -> Vec<CampusGroup> {
+    let mut curated: Vec<CampusGroup> = mapping
+        .campuses
+        .iter()
+        .map(|campus| CampusGroup {
+            key: campus.name.clone(),
+            name: campus.name.clone(),
+            coordinates: campus.center,
+            source: CampusSource::Curated,
+            schools: Vec::new(),
+        })
+        .collect();
+    let mut remaining: Vec<&CampusSchool> = Vec::new();
+    for school in schools.iter() {
+        let listed = mapping.campuses.iter().position(|campus| {
+            school
+                .code_uai
+                .as_ref()
+                .is_some_and(|code| campus.uai.contains(&code.to_uppercase()))
+        });
+        let nearest = mapping
+            .campuses
+            .iter()
+            .enumerate()
+            .map(|(index, campus)| (index, campus.center.distance_km(&school.coordinates), campus))
+            .filter(|(_, distance_km, campus)| *distance_km <= campus.radius_km)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _, _)| index);
+        match listed.or(nearest) {
+            Some(index) => curated[index].schools.push(school.idschool),
+            None => remaining.push(school),
+        }
+    }
+    remaining.sort_by(|a, b| a.code_uai.cmp(&b.code_uai).then(a.idschool.cmp(&b.idschool)));
+
+    // single linkage: schools are merged whenever two of them are close enough
+    let mut parents: Vec<usize> = (0..remaining.len()).collect();
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+    for i in 0..remaining.len() {
+        for j in i + 1..remaining.len() {
+            if remaining[i].coordinates.distance_km(&remaining[j].coordinates) <= mapping.cluster_km
+            {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut names: HashSet<String> = curated.iter().map(|campus| campus.name.clone()).collect();
+    let mut clusters: Vec<CampusGroup> = Vec::new();
+    for index in 0..remaining.len() {
+        if root(&mut parents, index) != index {
+            continue;
+        }
+        let members: Vec<&CampusSchool> = (index..remaining.len())
+            .filter(|member| root(&mut parents, *member) == index)
+            .map(|member| remaining[member])
+            .collect();
+        if members.len() < mapping.min_schools {
+            continue;
+        }
+        // schools without a UAI are keyed by their id, which a UAI can't be mistaken for
+        let keys: Vec<(String, &CampusSchool)> = members
+            .iter()
+            .map(|school| match &school.code_uai {
+                Some(code) => (code.to_uppercase(), *school),
+                None => (school.idschool.to_string(), *school),
+            })
+            .collect();
+        let (key, named) = keys
+            .into_iter()
+            .min_by(|(a, _), (b, _)| (!known.contains(a), a).cmp(&(!known.contains(b), b)))
+            .unwrap();
+        let mut name = named.name.clone();
+        if names.contains(&name) {
+            name = format!("{} ({})", named.name, key);
+        }
+        names.insert(name.clone());
+        let count = members.len() as f64;
+        clusters.push(CampusGroup {
+            key,
+            name,
+            coordinates: Coordinates {
+                lat: members.iter().map(|school| school.coordinates.lat).sum::<f64>() / count,
+                lon: members.iter().map(|school| school.coordinates.lon).sum::<f64>() / count,
+            },
+            source: CampusSource::Cluster,
+            schools: members.iter().map(|school| school.idschool).collect(),
+        });
+    }
+
+    curated
+        .into_iter()
+        .filter(|campus| !campus.schools.is_empty())
+        .chain(clusters)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn school(idschool: i32, code_uai: &str, name: &str, lat: f64, lon: f64) -> CampusSchool {
+        CampusSchool {
+            idschool,
+            code_uai: Some(code_uai.to_string()),
+            name: name.to_string(),
+            coordinates: Coordinates { lat, lon },
+        }
+    }
+
+    #[test]
+    fn test_group_campuses() {
+        let mapping = CampusMapping::from_json(
+            r#"{"version": 1, "cluster_km": 0.5, "campuses": [
+                {"name": "Triolet", "center": {"lat": 43.6318, "lon": 3.8621}, "radius_km": 0.5, "uai": ["0340001a"]},
+                {"name": "Richter", "center": {"lat": 43.6048, "lon": 3.8972}, "radius_km": 0.6}
+            ]}"#,
+        )
+        .unwrap();
+        let schools = vec![
+            school(1, "0341000A", "Faculté des Sciences", 43.6320, 3.8625),
+            // listed, far from the campus
+            school(2, "0340001A", "Polytech", 43.6600, 3.9000),
+            school(3, "0342000B", "IUT Nîmes", 43.8200, 4.3700),
+            school(4, "0342001B", "Université de Nîmes", 43.8220, 4.3690),
+            school(5, "0343000C", "IUT Béziers", 43.3400, 3.2200),
+        ];
+        let groups = group_campuses(&schools, &mapping, &HashSet::new());
+        // IUT Béziers is alone, no campus
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| (group.key.as_str(), group.name.as_str(), group.source, group.schools.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Triolet", "Triolet", CampusSource::Curated, vec![1, 2]),
+                ("0342000B", "IUT Nîmes", CampusSource::Cluster, vec![3, 4]),
+            ]
+        );
+        assert!((groups[1].coordinates.lat - 43.8210).abs() < 1e-9);
+
+        // a school with a lower UAI joining the cluster keeps its key and name
+        let mut joined = schools.clone();
+        joined.push(school(6, "0341999Z", "Gymnase Nîmes", 43.8210, 4.3695));
+        let known = HashSet::from(["0342000B".to_string()]);
+        let groups = group_campuses(&joined, &mapping, &known);
+        assert_eq!((groups[1].key.as_str(), groups[1].name.as_str()), ("0342000B", "IUT Nîmes"));
+        assert_eq!(groups[1].schools, vec![6, 3, 4]);
+        let groups = group_campuses(&joined, &mapping, &HashSet::new());
+        assert_eq!((groups[1].key.as_str(), groups[1].name.as_str()), ("0341999Z", "Gymnase Nîmes"));
+
+        let single = CampusMapping {
+            min_schools: 1,
+            ..mapping.clone()
+        };
+        assert_eq!(group_campuses(&schools, &single, &HashSet::new()).len(), 3);
+
+        assert!(CampusMapping::from_json(r#"{"version": 1, "cluster_km": 0.5, "min_schools": 0, "campuses": []}"#).is_err());
+        assert!(CampusMapping::from_json(
+            r#"{"version": 1, "cluster_km": 0.5, "campuses": [
+                {"name": "Triolet", "center": {"lat": 43.6, "lon": 3.8}, "radius_km": 0.5},
+                {"name": "Triolet", "center": {"lat": 43.6, "lon": 3.9}, "radius_km": 0.5}
+            ]}"#,
+        )
+        .is_err());
+    }
+}
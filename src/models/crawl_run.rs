@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use sqlx::{prelude::FromRow, PgConnection};
+
+// region of the CROUS website the crawler reads, when CRAWL_REGION isn't set
+pub const DEFAULT_REGION: &str = "montpellier";
+
+#[derive(Clone, Default)]
+pub struct CrawlRunService {}
+
+// items an action went through, filled while it runs
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RunCounts {
+    pub fetched: i32,
+    pub parsed: i32,
+    pub inserted: i32,
+    pub updated: i32,
+    pub skipped: i32,
+    pub errors: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn from_name(status: &str) -> Option<Self> {
+        match status {
+            "succeeded" => Some(Self::Succeeded),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+// a command run, as recorded in the crawl_run ledger
+#[derive(Debug, Clone)]
+pub struct CrawlRun {
+    pub idrun: i64,
+    pub action: String,
+    pub region: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub ended_at: chrono::DateTime<chrono::Utc>,
+    pub status: RunStatus,
+    pub counts: RunCounts,
+    pub message: String,
+}
+
+#[derive(FromRow)]
+pub(crate) struct CrawlRunRow {
+    idrun: i64,
+    action: String,
+    region: String,
+    started_at: chrono::DateTime<chrono::Utc>,
+    ended_at: chrono::DateTime<chrono::Utc>,
+    status: String,
+    fetched: i32,
+    parsed: i32,
+    inserted: i32,
+    updated: i32,
+    skipped: i32,
+    errors: i32,
+    message: Option<String>,
+}
+
+impl From<CrawlRunRow> for CrawlRun {
+    fn from(row: CrawlRunRow) -> Self {
+        Self {
+            idrun: row.idrun,
+            action: row.action,
+            region: row.region,
+            started_at: row.started_at,
+            ended_at: row.ended_at,
+            status: RunStatus::from_name(&row.status).unwrap_or(RunStatus::Failed),
+            counts: RunCounts {
+                fetched: row.fetched,
+                parsed: row.parsed,
+                inserted: row.inserted,
+                updated: row.updated,
+                skipped: row.skipped,
+                errors: row.errors,
+            },
+            message: row.message.unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) const CRAWL_RUN_COLUMNS: &str = "idrun, action, region, started_at, ended_at, status, fetched, parsed, inserted, updated, skipped, errors, message";
+
+// storage of the crawl_run ledger, through a connection of type C
+#[async_trait]
+pub trait CrawlRunRepository<C: Send>: Send + Sync {
+    // records a finished run, returns its id
+    async fn create(&self, conn: &mut C, run: &CrawlRun) -> Result<i64, sqlx::Error>;
+
+    // the latest runs first, of one action only when given
+    async fn find_all(
+        &self,
+        conn: &mut C,
+        action: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<CrawlRun>, sqlx::Error>;
+
+    async fn find(&self, conn: &mut C, idrun: i64) -> Result<Option<CrawlRun>, sqlx::Error>;
+}
+
+impl CrawlRunService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl CrawlRunRepository<PgConnection> for CrawlRunService {
+    async fn create(&self, conn: &mut PgConnection, run: &CrawlRun) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"INSERT INTO crawl_run(action, region, started_at, ended_at, status, fetched, parsed, inserted, updated, skipped, errors, message)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING idrun"#,
+        )
+        .bind(&run.action)
+        .bind(&run.region)
+        .bind(run.started_at)
+        .bind(run.ended_at)
+        .bind(run.status.as_str())
+        .bind(run.counts.fetched)
+        .bind(run.counts.parsed)
+        .bind(run.counts.inserted)
+        .bind(run.counts.updated)
+        .bind(run.counts.skipped)
+        .bind(run.counts.errors)
+        .bind(&run.message)
+        .fetch_one(&mut *conn)
+        .await
+    }
+
+    async fn find_all(
+        &self,
+        conn: &mut PgConnection,
+        action: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<CrawlRun>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, CrawlRunRow>(&format!(
+            r#"SELECT {} FROM crawl_run
+            WHERE $1::text IS NULL OR action = $1
+            ORDER BY started_at DESC, idrun DESC
+            LIMIT $2"#,
+            CRAWL_RUN_COLUMNS
+        ))
+        .bind(action)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(rows.into_iter().map(CrawlRun::from).collect())
+    }
+
+    async fn find(&self, conn: &mut PgConnection, idrun: i64) -> Result<Option<CrawlRun>, sqlx::Error> {
+        let row = sqlx::query_as::<_, CrawlRunRow>(&format!(
+            "SELECT {} FROM crawl_run WHERE idrun = $1",
+            CRAWL_RUN_COLUMNS
+        ))
+        .bind(idrun)
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(row.map(CrawlRun::from))
+    }
+}
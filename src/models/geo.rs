@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Coordinates {
+    // parses a postgres point as stored in restaurant.gpscoord, "(lat,lon)", also read as
+    // "point(lat,lon)" as it was once written
+    pub fn from_point(point: &str) -> Option<Self> {
+        let point = point.trim();
+        let point = match point.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("point") => &point[5..],
+            _ => point,
+        };
+        point.trim().trim_start_matches('(').trim_end_matches(')').parse().ok()
+    }
+
+    // the point as gpscoord and coords store it
+    pub fn to_point(self) -> String {
+        format!("({},{})", self.lat, self.lon)
+    }
+
+    // great-circle distance, with the haversine formula
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+// "43.6323,3.8652"
+impl FromStr for Coordinates {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid coordinates {:?}, expected lat,lon", text);
+        let (lat, lon) = text.split_once(',').ok_or_else(invalid)?;
+        let lat: f64 = lat.trim().parse().map_err(|_| invalid())?;
+        let lon: f64 = lon.trim().parse().map_err(|_| invalid())?;
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return Err(invalid());
+        }
+        Ok(Self { lat, lon })
+    }
+}
+
+// area between two corners, "min_lat,min_lon,max_lat,max_lon"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Coordinates,
+    pub max: Coordinates,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, point: &Coordinates) -> bool {
+        (self.min.lat..=self.max.lat).contains(&point.lat)
+            && (self.min.lon..=self.max.lon).contains(&point.lon)
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid bounding box {:?}, expected min_lat,min_lon,max_lat,max_lon", text);
+        let values: Vec<&str> = text.split(',').collect();
+        if values.len() != 4 {
+            return Err(invalid());
+        }
+        let min: Coordinates = format!("{},{}", values[0], values[1]).parse()?;
+        let max: Coordinates = format!("{},{}", values[2], values[3]).parse()?;
+        if min.lat > max.lat || min.lon > max.lon {
+            return Err(invalid());
+        }
+        Ok(Self { min, max })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coordinates() {
+        assert_eq!(
+            "43.6323, 3.8652".parse::<Coordinates>(),
+            Ok(Coordinates { lat: 43.6323, lon: 3.8652 })
+        );
+        assert_eq!(
+            Coordinates::from_point("(43.6323,3.8652)"),
+            Some(Coordinates { lat: 43.6323, lon: 3.8652 })
+        );
+        let triolet = Coordinates { lat: 43.6323, lon: 3.8652 };
+        assert_eq!(Coordinates::from_point("point(43.6323,3.8652)"), Some(triolet));
+        assert_eq!(Coordinates::from_point("POINT(43.6323, 3.8652)"), Some(triolet));
+        assert_eq!(Coordinates::from_point(&triolet.to_point()), Some(triolet));
+        assert!("43.6323".parse::<Coordinates>().is_err());
+        assert!("143.6,3.8".parse::<Coordinates>().is_err());
+    }
+
+    #[test]
+    fn test_distance_km() {
+        let triolet = Coordinates { lat: 43.6323, lon: 3.8652 };
+        let richter = Coordinates { lat: 43.6051, lon: 3.8985 };
+        let distance = triolet.distance_km(&richter);
+        assert!((distance - 4.0).abs() < 0.1, "{}", distance);
+        assert_eq!(triolet.distance_km(&triolet), 0.0);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let occitanie: BoundingBox = "42.3,-0.4,45.1,4.9".parse().unwrap();
+        assert!(occitanie.contains(&Coordinates { lat: 43.6323, lon: 3.8652 }));
+        assert!(!occitanie.contains(&Coordinates { lat: 48.85, lon: 2.35 }));
+        assert!("45.1,4.9,42.3,-0.4".parse::<BoundingBox>().is_err());
+        assert!("42.3,-0.4,45.1".parse::<BoundingBox>().is_err());
+    }
+}
@@ -1,19 +1,57 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use sqlx::PgPool;
+use async_trait::async_trait;
+use sqlx::{prelude::FromRow, PgConnection};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+use crate::{
+    models::{meals::Meal, restaurants::Restaurant},
+    text::{
+        fold,
+        synonyms::Synonyms,
+        tokenizer::{Token, Tokenizer},
+    },
+};
 
 pub struct Keyword {
     pub idsuggestion: i64,
     pub keyword: String,
+    pub folded: String,
+    pub stem: Option<String>,
     pub idrestaurant: i64,
     pub idcat: i64,
+    pub source: Source,
+    pub occurrences: i32,
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, FromRow, Clone)]
+pub struct Suggestion {
+    pub term: String,
+    pub category: String,
+    pub occurrences: i32,
+    pub score: f32,
+}
+
+// turns a text into keywords: its tokens, plus the aliases and canonical forms of the synonyms
+// found in it
+#[derive(Clone)]
+pub struct KeywordExtractor {
+    pub tokenizer: Tokenizer,
+    pub synonyms: Arc<Synonyms>,
 }
 
 #[derive(Clone)]
 pub struct KeywordService {
-    pub pool: Arc<PgPool>,
+    extractor: KeywordExtractor,
+    categories: Arc<OnceCell<CategoryRegistry>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Category {
     Meal,
     Restaurant,
@@ -21,33 +59,569 @@ pub enum Category {
 }
 
 impl Category {
-    pub fn to_int(&self) -> i64 {
+    pub const ALL: [Category; 3] = [Category::Meal, Category::Restaurant, Category::Food];
+
+    // name of the category in cat_suggestions
+    pub fn name(&self) -> &str {
         match self {
-            Category::Meal => 1,
-            Category::Restaurant => 2,
-            Category::Food => 3,
+            Category::Meal => "meal",
+            Category::Restaurant => "restaurant",
+            Category::Food => "food",
+        }
+    }
+}
+
+// what a keyword was extracted from, each source files its keywords under one category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    RestaurantName,
+    MealTitle,
+    Dish,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Source::RestaurantName => "restaurant_name",
+            Source::MealTitle => "meal_title",
+            Source::Dish => "dish",
+        }
+    }
+
+    pub fn from_name(source: &str) -> Option<Self> {
+        match source {
+            "restaurant_name" => Some(Source::RestaurantName),
+            "meal_title" => Some(Source::MealTitle),
+            "dish" => Some(Source::Dish),
+            _ => None,
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        match self {
+            Source::RestaurantName => Category::Restaurant,
+            Source::MealTitle => Category::Meal,
+            Source::Dish => Category::Food,
+        }
+    }
+}
+
+// keywords waiting to be written in one statement. The same keyword found several times for a
+// restaurant and category is kept once with its occurrences summed, since an upsert can't touch
+// the same row twice
+#[derive(Debug, Default)]
+pub struct KeywordBatch {
+    rows: HashMap<(String, i64, Category), KeywordRow>,
+}
+
+#[derive(Debug)]
+struct KeywordRow {
+    token: Token,
+    source: Source,
+    occurrences: i32,
+}
+
+impl KeywordBatch {
+    pub fn push(&mut self, token: Token, idrestaurant: i64, source: Source) {
+        let key = (token.display.clone(), idrestaurant, source.category());
+        self.rows
+            .entry(key)
+            .and_modify(|row| row.occurrences += 1)
+            .or_insert(KeywordRow {
+                token,
+                source,
+                occurrences: 1,
+            });
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    // every keyword with its restaurant, source and occurrences
+    pub fn into_rows(self) -> impl Iterator<Item = (Token, i64, Source, i32)> {
+        self.rows
+            .into_iter()
+            .map(|((_, idrestaurant, _), row)| (row.token, idrestaurant, row.source, row.occurrences))
+    }
+}
+
+// a keyword as stored, read back to build the suggestions
+#[cfg(any(test, feature = "sqlite"))]
+pub struct StoredKeyword {
+    pub keyword: String,
+    pub folded: String,
+    pub stem: String,
+    pub idrestaurant: i64,
+    pub category: Category,
+    pub occurrences: i32,
+}
+
+// a term of the suggestion index
+#[cfg(any(test, feature = "sqlite"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedTerm {
+    pub term: String,
+    pub folded: String,
+    pub stem: String,
+    pub category: Category,
+    pub occurrences: i32,
+    pub restaurants: i32,
+}
+
+// keywords sharing a stem within a category are merged under their most frequent spelling
+// ("frites" and "frite"), as refresh_suggestions does in Postgres
+#[cfg(any(test, feature = "sqlite"))]
+pub fn merge_keywords(keywords: Vec<StoredKeyword>) -> Vec<IndexedTerm> {
+    let mut terms: HashMap<(String, Category), (IndexedTerm, i32, HashSet<i64>)> = HashMap::new();
+    for keyword in keywords {
+        let (term, best, restaurants) = terms
+            .entry((keyword.stem.clone(), keyword.category))
+            .or_insert_with(|| {
+                let term = IndexedTerm {
+                    term: keyword.keyword.clone(),
+                    folded: keyword.folded.clone(),
+                    stem: keyword.stem.clone(),
+                    category: keyword.category,
+                    occurrences: 0,
+                    restaurants: 0,
+                };
+                (term, keyword.occurrences, HashSet::new())
+            });
+        term.occurrences += keyword.occurrences;
+        if keyword.occurrences > *best {
+            *best = keyword.occurrences;
+            term.term = keyword.keyword;
+            term.folded = keyword.folded;
+        }
+        restaurants.insert(keyword.idrestaurant);
+    }
+    let mut terms: Vec<IndexedTerm> = terms
+        .into_values()
+        .map(|(mut term, _, restaurants)| {
+            term.restaurants = restaurants.len() as i32;
+            term
+        })
+        .collect();
+    terms.sort_by(|a, b| (&a.stem, a.category.name()).cmp(&(&b.stem, b.category.name())));
+    terms
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryRegistry {
+    ids: HashMap<Category, i32>,
+}
+
+impl CategoryRegistry {
+    // resolves every category by name from cat_suggestions, creating the missing ones. Fails if a
+    // name is ambiguous, since keywords would then be filed under an arbitrary id
+    pub async fn sync(conn: &mut PgConnection) -> Result<Self, sqlx::Error> {
+        let rows = sqlx::query_as::<_, (i32, Option<String>)>(
+            "SELECT idcat, namecat FROM cat_suggestions ORDER BY idcat",
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut ids = HashMap::new();
+        for category in Category::ALL {
+            let matching: Vec<i32> = rows
+                .iter()
+                .filter(|(_, name)| {
+                    name.as_deref()
+                        .is_some_and(|name| name.trim().eq_ignore_ascii_case(category.name()))
+                })
+                .map(|(idcat, _)| *idcat)
+                .collect();
+            let idcat = match matching.as_slice() {
+                [idcat] => *idcat,
+                [] => {
+                    warn!("category {} missing from cat_suggestions, creating it", category.name());
+                    sqlx::query_scalar::<_, i32>(
+                        "INSERT INTO cat_suggestions(namecat) VALUES ($1) RETURNING idcat",
+                    )
+                    .bind(category.name())
+                    .fetch_one(&mut *conn)
+                    .await?
+                }
+                duplicates => {
+                    return Err(sqlx::Error::Configuration(
+                        format!(
+                            "category {} is defined several times in cat_suggestions (ids {:?})",
+                            category.name(),
+                            duplicates
+                        )
+                        .into(),
+                    ));
+                }
+            };
+            ids.insert(category, idcat);
+        }
+
+        for (idcat, name) in rows.iter() {
+            if !ids.values().any(|id| id == idcat) {
+                warn!("unknown category in cat_suggestions: {} ({:?})", idcat, name);
+            }
+        }
+        info!(
+            "categories: {}",
+            Category::ALL
+                .iter()
+                .map(|category| format!("{}={}", category.name(), ids[category]))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Ok(Self { ids })
+    }
+
+    pub fn id(&self, category: Category) -> i32 {
+        self.ids[&category]
+    }
+}
+
+impl KeywordExtractor {
+    pub fn new(synonyms: Arc<Synonyms>) -> Self {
+        Self {
+            tokenizer: Tokenizer::new(),
+            synonyms,
+        }
+    }
+
+    pub fn keywords(&self, text: &str, restaurant_url: Option<&str>) -> Vec<Token> {
+        let mut tokens = self.tokenizer.tokenize(text);
+        for synonym in self.synonyms.matching(text, restaurant_url) {
+            for term in [&synonym.alias, &synonym.canonical] {
+                let token = self.term_token(term);
+                if !tokens.iter().any(|known| known.folded == token.folded) {
+                    tokens.push(token);
+                }
+            }
+        }
+        tokens
+    }
+
+    // a term of several words kept whole, such as a synonym or a restaurant name
+    pub fn term_token(&self, term: &str) -> Token {
+        Token {
+            display: term.to_lowercase(),
+            folded: fold(term),
+            stem: self.tokenizer.stemmer().stem(term),
+        }
+    }
+
+    // tokenizes a text (dish, meal title...) into the batch
+    pub fn collect(&self, batch: &mut KeywordBatch, text: &str, idrestaurant: i64, source: Source) {
+        for token in self.keywords(text, None) {
+            batch.push(token, idrestaurant, source);
         }
     }
+
+    // same as collect, with the synonyms tied to the restaurant url
+    pub fn collect_restaurant(&self, batch: &mut KeywordBatch, restaurant: &Restaurant) {
+        if let Some(idrestaurant) = restaurant.idrestaurant {
+            for token in self.keywords(&restaurant.name, Some(&restaurant.url)) {
+                batch.push(token, i64::from(idrestaurant), Source::RestaurantName);
+            }
+        }
+    }
+
+    // keywords the current restaurants and meals would produce, keyed by restaurant, keyword and
+    // source
+    pub fn supported(
+        &self,
+        restaurants: &[Restaurant],
+        meals: &[Meal],
+    ) -> HashSet<(i64, String, Source)> {
+        let mut supported = HashSet::new();
+        for restaurant in restaurants.iter() {
+            if let Some(idrestaurant) = restaurant.idrestaurant {
+                for token in self.keywords(&restaurant.name, Some(&restaurant.url)) {
+                    supported.insert((i64::from(idrestaurant), token.display, Source::RestaurantName));
+                }
+            }
+        }
+        for meal in meals.iter() {
+            for token in self.keywords(&meal.typemeal, None) {
+                supported.insert((meal.idrestaurant, token.display, Source::MealTitle));
+            }
+            for dish in meal.foodies.iter().flat_map(|foody| foody.content.iter()) {
+                for token in self.keywords(dish, None) {
+                    supported.insert((meal.idrestaurant, token.display, Source::Dish));
+                }
+            }
+        }
+        supported
+    }
+}
+
+// storage of the keywords and of the suggestions built from them, in Postgres or in memory,
+// through a connection of type C
+#[async_trait]
+pub trait KeywordRepository<C: Send>: Send + Sync {
+    fn extractor(&self) -> &KeywordExtractor;
+
+    // makes sure every keyword category can be written, storages keeping them by name have
+    // nothing to check
+    async fn check_categories(&self, _conn: &mut C) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    // writes the whole batch at once. A keyword already known for this restaurant and category
+    // only gets its occurrence count increased and its last_seen_at refreshed
+    async fn create_many(&self, conn: &mut C, batch: KeywordBatch) -> Result<u64, sqlx::Error>;
+
+    // deletes the keywords not seen since older_than that no current data supports anymore.
+    // Recently seen keywords are kept even when unsupported, since meals are cleaned before
+    // every run
+    async fn gc(
+        &self,
+        conn: &mut C,
+        older_than: chrono::Duration,
+        supported: &HashSet<(i64, String, Source)>,
+    ) -> Result<u64, sqlx::Error>;
+
+    // rebuilds the suggestions from the keywords of every restaurant
+    async fn refresh_suggestions(&self, conn: &mut C) -> Result<(), sqlx::Error>;
+
+    // terms matching the prefix, the best first
+    async fn suggest(
+        &self,
+        conn: &mut C,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<Suggestion>, sqlx::Error>;
 }
 
 impl KeywordService {
-    pub fn new(pool: Arc<PgPool>) -> Self {
-        Self { pool }
+    pub fn new(synonyms: Arc<Synonyms>) -> Self {
+        Self {
+            extractor: KeywordExtractor::new(synonyms),
+            categories: Arc::new(OnceCell::new()),
+        }
+    }
+
+    // same as KeywordRepository::extractor, without having to pick a connection type
+    #[cfg(feature = "sqlite")]
+    pub fn extractor(&self) -> &KeywordExtractor {
+        &self.extractor
+    }
+
+    // categories are synced with cat_suggestions once, on first use
+    pub async fn categories(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<&CategoryRegistry, sqlx::Error> {
+        self.categories
+            .get_or_try_init(|| CategoryRegistry::sync(conn))
+            .await
+    }
+
+}
+
+#[async_trait]
+impl KeywordRepository<PgConnection> for KeywordService {
+    fn extractor(&self) -> &KeywordExtractor {
+        &self.extractor
+    }
+
+    async fn check_categories(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        self.categories(conn).await.map(|_| ())
     }
-    pub async fn create(
+
+    // one UNNEST statement
+    async fn create_many(
         &self,
-        keyword: String,
-        idrestaurant: i64,
-        category: Category,
-    ) -> Result<(), sqlx::Error> {
+        conn: &mut PgConnection,
+        batch: KeywordBatch,
+    ) -> Result<u64, sqlx::Error> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let categories = self.categories(&mut *conn).await?;
+        let mut keywords = Vec::with_capacity(batch.len());
+        let mut folded = Vec::with_capacity(batch.len());
+        let mut stems = Vec::with_capacity(batch.len());
+        let mut idrestaurants = Vec::with_capacity(batch.len());
+        let mut idcats = Vec::with_capacity(batch.len());
+        let mut sources = Vec::with_capacity(batch.len());
+        let mut occurrences = Vec::with_capacity(batch.len());
+        for (token, idrestaurant, source, count) in batch.into_rows() {
+            keywords.push(token.display);
+            folded.push(token.folded);
+            stems.push(token.stem);
+            idrestaurants.push(idrestaurant);
+            idcats.push(categories.id(source.category()));
+            sources.push(source.as_str().to_string());
+            occurrences.push(count);
+        }
+        let result = sqlx::query(
+            r#"INSERT INTO suggestions_restaurant(keyword, folded, stem, idrestaurant, idcat, source, occurrences)
+            SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::int8[], $5::int4[], $6::text[], $7::int4[])
+            ON CONFLICT (keyword, idrestaurant, idcat)
+            DO UPDATE SET occurrences = suggestions_restaurant.occurrences + EXCLUDED.occurrences, last_seen_at = now(), folded = EXCLUDED.folded, stem = EXCLUDED.stem, source = EXCLUDED.source"#,
+        )
+        .bind(keywords)
+        .bind(folded)
+        .bind(stems)
+        .bind(idrestaurants)
+        .bind(idcats)
+        .bind(sources)
+        .bind(occurrences)
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn gc(
+        &self,
+        conn: &mut PgConnection,
+        older_than: chrono::Duration,
+        supported: &HashSet<(i64, String, Source)>,
+    ) -> Result<u64, sqlx::Error> {
+        let threshold = chrono::Utc::now() - older_than;
+        let candidates = sqlx::query_as::<_, (i64, String, i64, String)>(
+            r#"SELECT idsuggestion::bigint, keyword, idrestaurant::bigint, source
+            FROM suggestions_restaurant
+            WHERE last_seen_at < $1"#,
+        )
+        .bind(threshold)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let stale: Vec<i64> = candidates
+            .into_iter()
+            .filter(|(_, keyword, idrestaurant, source)| match Source::from_name(source) {
+                Some(source) => !supported.contains(&(*idrestaurant, keyword.clone(), source)),
+                None => true,
+            })
+            .map(|(idsuggestion, _, _, _)| idsuggestion)
+            .collect();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+
+        let deleted = sqlx::query("DELETE FROM suggestions_restaurant WHERE idsuggestion = ANY($1)")
+            .bind(stale)
+            .execute(&mut *conn)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+
+    // suggestion_index also gets the full name of each restaurant so that "ru richter" can match
+    // "Resto U' Richter". Keywords sharing a stem are merged under their most frequent spelling
+    // ("frites" and "frite")
+    async fn refresh_suggestions(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        let idcat = self.categories(&mut *conn).await?.id(Category::Restaurant);
+        // restaurant names are folded and stemmed here, as the keywords were
+        let names = sqlx::query_scalar::<_, String>("SELECT name FROM restaurant WHERE name IS NOT NULL")
+            .fetch_all(&mut *conn)
+            .await?;
+        let tokens: Vec<Token> = names.iter().map(|name| self.extractor.term_token(name)).collect();
+        sqlx::query("DELETE FROM suggestion_index")
+            .execute(&mut *conn)
+            .await?;
         sqlx::query(
-            r#"INSERT INTO suggestions_restaurant(keyword, idrestaurant, idcat) VALUES ($1, $2, $3)"#,
+            r#"INSERT INTO suggestion_index(term, folded, stem, idcat, occurrences, restaurants)
+            SELECT (array_agg(keyword ORDER BY occurrences DESC))[1], (array_agg(folded ORDER BY occurrences DESC))[1],
+                coalesce(stem, folded), idcat, sum(occurrences), count(DISTINCT idrestaurant)
+            FROM suggestions_restaurant
+            WHERE folded IS NOT NULL
+            GROUP BY coalesce(stem, folded), idcat
+            UNION ALL
+            SELECT min(name), folded, min(stem), $1, count(*), count(*)
+            FROM UNNEST($2::text[], $3::text[], $4::text[]) AS names(name, folded, stem)
+            GROUP BY folded
+            ON CONFLICT (stem, idcat) DO NOTHING"#,
         )
-        .bind(keyword)
-        .bind(idrestaurant)
-        .bind(category.to_int())
-        .execute(self.pool.as_ref())
+        .bind(idcat)
+        .bind(names)
+        .bind(tokens.iter().map(|token| token.folded.clone()).collect::<Vec<_>>())
+        .bind(tokens.into_iter().map(|token| token.stem).collect::<Vec<_>>())
+        .execute(&mut *conn)
         .await?;
         Ok(())
     }
+
+    // fuzzy autocomplete: terms starting with the prefix or sharing its stem come first, then the
+    // closest ones by trigram similarity, weighted by how often they were seen
+    async fn suggest(
+        &self,
+        conn: &mut PgConnection,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<Suggestion>, sqlx::Error> {
+        let folded = self.extractor.synonyms.expand(prefix);
+        if folded.is_empty() {
+            return Ok(Vec::new());
+        }
+        let stem = self.extractor.tokenizer.stemmer().stem(&folded);
+        let pattern = format!(
+            "{}%",
+            folded.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        let suggestions = sqlx::query_as::<_, Suggestion>(
+            r#"SELECT s.term, c.namecat AS category, s.occurrences, matches.score
+            FROM suggestion_index s
+            JOIN cat_suggestions c ON c.idcat = s.idcat,
+            LATERAL (
+                SELECT ((s.folded LIKE $2 OR s.stem = $4)::int + greatest(similarity(s.folded, $1), word_similarity($1, s.folded)))::real AS score
+            ) matches
+            WHERE s.folded LIKE $2 OR s.stem = $4 OR s.folded % $1 OR $1 <% s.folded
+            ORDER BY matches.score * (1 + ln(1 + s.occurrences) / 10) DESC, s.term
+            LIMIT $3"#,
+        )
+        .bind(folded)
+        .bind(pattern)
+        .bind(limit)
+        .bind(stem)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(suggestions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(display: &str) -> Token {
+        Token {
+            display: display.to_string(),
+            folded: fold(display),
+            stem: display.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_keywords() {
+        let keyword = |keyword: &str, idrestaurant: i64, occurrences: i32| StoredKeyword {
+            keyword: keyword.to_string(),
+            folded: fold(keyword),
+            stem: "frit".to_string(),
+            idrestaurant,
+            category: Category::Food,
+            occurrences,
+        };
+        let terms = merge_keywords(vec![keyword("frite", 1, 1), keyword("frites", 1, 3), keyword("frites", 2, 2)]);
+        assert_eq!(terms.len(), 1);
+        assert_eq!(terms[0].term, "frites");
+        assert_eq!(terms[0].occurrences, 6);
+        assert_eq!(terms[0].restaurants, 2);
+    }
+
+    #[test]
+    fn test_batch_sums_occurrences() {
+        let mut batch = KeywordBatch::default();
+        batch.push(token("frites"), 1, Source::Dish);
+        batch.push(token("frites"), 1, Source::Dish);
+        batch.push(token("frites"), 1, Source::MealTitle);
+        batch.push(token("frites"), 2, Source::Dish);
+        assert_eq!(batch.len(), 3);
+        let key = ("frites".to_string(), 1, Category::Food);
+        assert_eq!(batch.rows[&key].occurrences, 2);
+    }
 }
@@ -1,43 +1,506 @@
 use std::sync::Arc;
 
-use sqlx::PgPool;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, Connection, PgConnection};
 
-use crate::cli::actions::meals::Foody;
+use crate::{
+    cli::actions::meals::Foody,
+    dietary::MealTags,
+    models::{
+        geo::Coordinates,
+        search::prefix_tsquery,
+    },
+    text::{fold, synonyms::Synonyms, tokenizer::Tokenizer},
+};
+#[cfg(any(test, feature = "sqlite"))]
+use crate::models::search::query_tokens;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct MealService {
-    pub pool: Arc<PgPool>,
+    pub tokenizer: Tokenizer,
+    pub synonyms: Arc<Synonyms>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Meal {
     pub typemeal: String,
+    pub service: Service,
     pub foodies: sqlx::types::Json<Vec<Foody>>,
+    pub tags: sqlx::types::Json<MealTags>,
     pub day: chrono::DateTime<chrono::Utc>,
     pub idrestaurant: i64,
 }
 
+// a restaurant serving dishes matching a query, for one of its services of the day
+#[derive(Debug, Clone)]
+pub struct DishMatch {
+    pub idrestaurant: i32,
+    pub name: String,
+    pub url: String,
+    pub service: Service,
+    pub dishes: Vec<String>,
+    pub distance_km: Option<f64>,
+}
+
+#[derive(FromRow)]
+struct DishMatchRow {
+    idrestaurant: i32,
+    name: String,
+    url: String,
+    service: Option<String>,
+    dishes: Vec<String>,
+    gpscoord: Option<String>,
+}
+
+// meal as stored, typemeal and service may be missing on rows older than the service column
+#[derive(FromRow)]
+struct MealRow {
+    typemeal: Option<String>,
+    service: Option<String>,
+    foodies: Option<sqlx::types::Json<Vec<Foody>>>,
+    tags: Option<sqlx::types::Json<MealTags>>,
+    day: chrono::DateTime<chrono::Utc>,
+    idrestaurant: i64,
+}
+
+impl From<MealRow> for Meal {
+    fn from(row: MealRow) -> Self {
+        Self {
+            typemeal: row.typemeal.unwrap_or_default(),
+            service: Service::from(row.service.unwrap_or_default()),
+            foodies: row.foodies.unwrap_or_default(),
+            tags: row.tags.unwrap_or_default(),
+            day: row.day,
+            idrestaurant: row.idrestaurant,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Service {
+    Breakfast,
+    Lunch,
+    Dinner,
+    AllDay,
+    Other(String),
+}
+
+impl Service {
+    pub fn from_label(label: &str) -> Self {
+        let normalized = normalize_label(label);
+        let words: Vec<&str> = normalized.split(' ').collect();
+        let matches = |keywords: &[&str]| keywords.iter().any(|keyword| has_words(&words, keyword));
+        // the afternoon is no lunch, even though it says "midi"
+        if matches(&["apres midi", "gouter"]) {
+            Self::Other(label.trim().to_string())
+        } else if matches(&["petit dejeuner", "breakfast"]) {
+            Self::Breakfast
+        } else if matches(&["dejeuner", "midi", "lunch"]) {
+            Self::Lunch
+        } else if matches(&["diner", "soir", "soiree", "dinner"]) {
+            Self::Dinner
+        } else if matches(&["journee", "continu", "non stop", "all day"]) {
+            Self::AllDay
+        } else {
+            Self::Other(label.trim().to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Breakfast => "breakfast",
+            Self::Lunch => "lunch",
+            Self::Dinner => "dinner",
+            Self::AllDay => "all_day",
+            Self::Other(label) => label,
+        }
+    }
+}
+
+impl From<String> for Service {
+    fn from(label: String) -> Self {
+        Self::from_label(&label)
+    }
+}
+
+impl From<Service> for String {
+    fn from(service: Service) -> Self {
+        service.as_str().to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Course {
+    Starter,
+    Main,
+    Side,
+    Dessert,
+    Cheese,
+    Drink,
+    PizzaGrill,
+    Other(String),
+}
+
+impl Course {
+    pub fn from_label(label: &str) -> Self {
+        let normalized = normalize_label(label);
+        let words: Vec<&str> = normalized.split(' ').collect();
+        let matches = |keywords: &[&str]| keywords.iter().any(|keyword| has_words(&words, keyword));
+        if matches(&["entree", "hors d'oeuvre", "starter"]) {
+            Self::Starter
+        } else if matches(&["pizza", "grill", "grillade", "stand"]) {
+            Self::PizzaGrill
+        } else if matches(&["accompagnement", "garniture", "legume", "side"]) {
+            Self::Side
+        } else if matches(&["dessert", "patisserie"]) {
+            Self::Dessert
+        } else if matches(&["fromage", "laitage", "laitier", "cheese"]) {
+            Self::Cheese
+        } else if matches(&["boisson", "drink"]) {
+            Self::Drink
+        } else if matches(&["plat", "cuisine", "traditionnel", "du jour", "main"]) {
+            Self::Main
+        } else {
+            Self::Other(label.trim().to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Starter => "starter",
+            Self::Main => "main",
+            Self::Side => "side",
+            Self::Dessert => "dessert",
+            Self::Cheese => "cheese",
+            Self::Drink => "drink",
+            Self::PizzaGrill => "pizza_grill",
+            Self::Other(label) => label,
+        }
+    }
+}
+
+// unknown labels are stored as they are, like the services, and classified again when read, which
+// gives them back since they matched no keyword
+impl From<String> for Course {
+    fn from(label: String) -> Self {
+        Self::from_label(&label)
+    }
+}
+
+impl From<Course> for String {
+    fn from(course: Course) -> Self {
+        course.as_str().to_string()
+    }
+}
+
+// whether the words of a label contain those of the keyword, in a row and each one whole or in
+// the plural, so "stand" matches "Stand pizza" but not "Plat standard"
+fn has_words(words: &[&str], keyword: &str) -> bool {
+    let keyword: Vec<&str> = keyword.split(' ').collect();
+    words.windows(keyword.len()).any(|window| {
+        window.iter().zip(keyword.iter()).all(|(word, expected)| {
+            *word == *expected
+                || word
+                    .strip_prefix(expected)
+                    .is_some_and(|suffix| suffix == "s" || suffix == "x")
+        })
+    })
+}
+
+// lowercases a CROUS label and strips its accents, so "Déjeuner" and "dejeuner" compare equal
+fn normalize_label(label: &str) -> String {
+    fold(label)
+        .replace(['-', '_'], " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// storage of the meals, in Postgres or in memory, through a connection of type C
+#[async_trait]
+pub trait MealRepository<C: Send>: Send + Sync {
+    // inserts every meal at once, or none of them: a failure leaves the command transaction
+    // usable
+    async fn create_many(&self, conn: &mut C, meals: &[Meal]) -> Result<u64, sqlx::Error>;
+
+    async fn find_all(&self, conn: &mut C) -> Result<Vec<Meal>, sqlx::Error>;
+
+    // restaurants serving, on the given day, dishes matching every word of the query once its
+    // aliases are expanded (on their stems, so "frite" finds "Frites" and "pdt" "Pommes de
+    // terre"). The closest restaurants come first when a point is given
+    async fn find_dish(
+        &self,
+        conn: &mut C,
+        query: &str,
+        day: chrono::NaiveDate,
+        near: Option<Coordinates>,
+    ) -> Result<Vec<DishMatch>, sqlx::Error>;
+
+    async fn clean(&self, conn: &mut C) -> Result<(), sqlx::Error>;
+}
+
 impl MealService {
-    pub fn new(pool: Arc<PgPool>) -> Self {
-        Self { pool }
+    pub fn new(synonyms: Arc<Synonyms>) -> Self {
+        Self {
+            tokenizer: Tokenizer::new(),
+            synonyms,
+        }
     }
-    pub async fn create(&self, meal: &Meal) -> Result<(), sqlx::Error> {
-        sqlx::query(
-            r#"INSERT INTO meal(typemeal, foodies, day, idrestaurant) VALUES ($1, $2, $3, $4)"#,
+}
+
+#[async_trait]
+impl MealRepository<PgConnection> for MealService {
+    // one statement, column by column, in a savepoint since a failed statement aborts the whole
+    // Postgres transaction
+    async fn create_many(&self, conn: &mut PgConnection, meals: &[Meal]) -> Result<u64, sqlx::Error> {
+        if meals.is_empty() {
+            return Ok(0);
+        }
+        let mut savepoint = conn.begin().await?;
+        let result = sqlx::query(
+            r#"INSERT INTO meal(typemeal, service, foodies, tags, day, idrestaurant)
+            SELECT * FROM UNNEST($1::text[], $2::text[], $3::jsonb[], $4::jsonb[], $5::timestamptz[], $6::int8[])"#,
         )
-        .bind(&meal.typemeal)
-        .bind(&meal.foodies)
-        .bind(meal.day)
-        .bind(meal.idrestaurant)
-        .execute(self.pool.as_ref())
+        .bind(meals.iter().map(|meal| meal.typemeal.as_str()).collect::<Vec<_>>())
+        .bind(meals.iter().map(|meal| meal.service.as_str()).collect::<Vec<_>>())
+        .bind(meals.iter().map(|meal| sqlx::types::Json(&meal.foodies.0)).collect::<Vec<_>>())
+        .bind(meals.iter().map(|meal| sqlx::types::Json(&meal.tags.0)).collect::<Vec<_>>())
+        .bind(meals.iter().map(|meal| meal.day).collect::<Vec<_>>())
+        .bind(meals.iter().map(|meal| meal.idrestaurant).collect::<Vec<_>>())
+        .execute(&mut *savepoint)
         .await?;
-        Ok(())
+        savepoint.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn find_all(&self, conn: &mut PgConnection) -> Result<Vec<Meal>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MealRow>(
+            r#"SELECT typemeal, service, foodies, tags, day::timestamp AT TIME ZONE 'UTC' AS day, idrestaurant::bigint AS idrestaurant
+            FROM meal
+            WHERE day IS NOT NULL AND idrestaurant IS NOT NULL"#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(rows.into_iter().map(Meal::from).collect())
+    }
+
+    async fn find_dish(
+        &self,
+        conn: &mut PgConnection,
+        query: &str,
+        day: chrono::NaiveDate,
+        near: Option<Coordinates>,
+    ) -> Result<Vec<DishMatch>, sqlx::Error> {
+        let tsquery = match prefix_tsquery(&self.tokenizer, &self.synonyms.expand(query)) {
+            Some(tsquery) => tsquery,
+            None => return Ok(Vec::new()),
+        };
+        let rows = sqlx::query_as::<_, DishMatchRow>(
+            r#"SELECT r.idrestaurant, r.name, r.url, m.service,
+                array_agg(DISTINCT dish ORDER BY dish) AS dishes, r.gpscoord::text AS gpscoord
+            FROM meal m
+            JOIN restaurant r ON r.idrestaurant = m.idrestaurant,
+            to_tsquery('simple', $1) q,
+            jsonb_array_elements(m.foodies) foody,
+            jsonb_array_elements_text(foody->'content') dish
+            WHERE m.day = $2
+                AND m.search @@ q
+                AND to_tsvector('public.french_unaccent', dish) @@ q
+            GROUP BY r.idrestaurant, r.name, r.url, m.service, r.gpscoord::text
+            ORDER BY r.name, m.service"#,
+        )
+        .bind(tsquery)
+        .bind(day)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut matches: Vec<DishMatch> = rows
+            .into_iter()
+            .map(|row| DishMatch {
+                distance_km: near.and_then(|near| {
+                    row.gpscoord
+                        .as_deref()
+                        .and_then(Coordinates::from_point)
+                        .map(|coordinates| near.distance_km(&coordinates))
+                }),
+                idrestaurant: row.idrestaurant,
+                name: row.name,
+                url: row.url,
+                service: Service::from(row.service.unwrap_or_default()),
+                dishes: row.dishes,
+            })
+            .collect();
+        if near.is_some() {
+            sort_by_distance(&mut matches);
+        }
+        Ok(matches)
     }
 
-    pub async fn clean(&self) -> Result<(), sqlx::Error> {
+    async fn clean(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
         sqlx::query("DELETE FROM meal")
-            .execute(self.pool.as_ref())
+            .execute(&mut *conn)
             .await?;
         Ok(())
     }
 }
+
+// a meal with the restaurant serving it, for the storages matching dishes without a full text
+// search
+#[cfg(any(test, feature = "sqlite"))]
+pub struct ServedMeal {
+    pub meal: Meal,
+    pub idrestaurant: i32,
+    pub name: String,
+    pub url: String,
+    pub coordinates: Option<Coordinates>,
+}
+
+// dishes matching every word of the query on a prefix of their stems, grouped per restaurant and
+// service as find_dish does in Postgres
+#[cfg(any(test, feature = "sqlite"))]
+pub fn match_dishes(
+    tokenizer: &Tokenizer,
+    query: &str,
+    served: Vec<ServedMeal>,
+    near: Option<Coordinates>,
+) -> Vec<DishMatch> {
+    let stems = |text: &str| {
+        tokenizer
+            .tokenize(text)
+            .into_iter()
+            .map(|token| token.stem)
+            .collect::<Vec<_>>()
+    };
+    let words: Vec<String> = query_tokens(tokenizer, query)
+        .into_iter()
+        .map(|token| token.stem)
+        .collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<DishMatch> = Vec::new();
+    for served in served {
+        let mut dishes: Vec<String> = served
+            .meal
+            .foodies
+            .iter()
+            .flat_map(|foody| foody.content.iter())
+            .filter(|dish| {
+                let dish = stems(dish);
+                words
+                    .iter()
+                    .all(|word| dish.iter().any(|stem| stem.starts_with(word.as_str())))
+            })
+            .cloned()
+            .collect();
+        if dishes.is_empty() {
+            continue;
+        }
+        let known = matches.iter_mut().find(|found| {
+            found.idrestaurant == served.idrestaurant && found.service == served.meal.service
+        });
+        match known {
+            Some(found) => found.dishes.append(&mut dishes),
+            None => matches.push(DishMatch {
+                idrestaurant: served.idrestaurant,
+                name: served.name,
+                url: served.url,
+                service: served.meal.service,
+                dishes,
+                distance_km: near
+                    .zip(served.coordinates)
+                    .map(|(near, coordinates)| near.distance_km(&coordinates)),
+            }),
+        }
+    }
+    for found in matches.iter_mut() {
+        found.dishes.sort();
+        found.dishes.dedup();
+    }
+    matches.sort_by(|a, b| (&a.name, a.service.as_str()).cmp(&(&b.name, b.service.as_str())));
+    if near.is_some() {
+        sort_by_distance(&mut matches);
+    }
+    matches
+}
+
+// restaurants without coordinates come last
+pub fn sort_by_distance(matches: &mut [DishMatch]) {
+    matches.sort_by(|a, b| match (a.distance_km, b.distance_km) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_from_label() {
+        assert_eq!(Service::from_label("Petit-déjeuner"), Service::Breakfast);
+        assert_eq!(Service::from_label(" Déjeuner "), Service::Lunch);
+        assert_eq!(Service::from_label("DINER"), Service::Dinner);
+        assert_eq!(Service::from_label("Service en continu"), Service::AllDay);
+        assert_eq!(
+            Service::from_label("Goûter"),
+            Service::Other("Goûter".to_string())
+        );
+        assert_eq!(
+            Service::from_label("Après-midi"),
+            Service::Other("Après-midi".to_string())
+        );
+        assert_eq!(Service::from_label("Soirée"), Service::Dinner);
+        assert_eq!(
+            Service::from_label("Espace Soirap"),
+            Service::Other("Espace Soirap".to_string())
+        );
+        assert_eq!(Service::from_label("Midis"), Service::Lunch);
+    }
+
+    #[test]
+    fn test_course_from_label() {
+        assert_eq!(Course::from_label("Entrées"), Course::Starter);
+        assert_eq!(Course::from_label("Plats chauds"), Course::Main);
+        assert_eq!(Course::from_label("Accompagnements"), Course::Side);
+        assert_eq!(Course::from_label("Stand pizza"), Course::PizzaGrill);
+        assert_eq!(Course::from_label("Laitages"), Course::Cheese);
+        assert_eq!(Course::from_label("main"), Course::Main);
+        assert_eq!(Course::from_label("Snack"), Course::Other("Snack".to_string()));
+        assert_eq!(Course::from_label("Plat standard"), Course::Main);
+        assert_eq!(Course::from_label("Hors d'oeuvres"), Course::Starter);
+        assert_eq!(Course::from_label("Grillades"), Course::PizzaGrill);
+        assert_eq!(
+            Course::from_label("Consideration"),
+            Course::Other("Consideration".to_string())
+        );
+    }
+
+    #[test]
+    fn test_course_round_trip() {
+        let json = serde_json::to_string(&Course::PizzaGrill).unwrap();
+        assert_eq!(json, "\"pizza_grill\"");
+        let course: Course = serde_json::from_str(&json).unwrap();
+        assert_eq!(course, Course::PizzaGrill);
+
+        let json = serde_json::to_string(&Course::from_label(" Snack ")).unwrap();
+        assert_eq!(json, "\"Snack\"");
+        let course: Course = serde_json::from_str(&json).unwrap();
+        assert_eq!(course, Course::Other("Snack".to_string()));
+
+        let service: Service = serde_json::from_str("\"Goûter\"").unwrap();
+        assert_eq!(String::from(service), "Goûter");
+    }
+
+    #[test]
+    fn test_foody_without_course() {
+        let foody: Foody =
+            serde_json::from_str(r#"{"type": "Desserts", "content": ["Tarte aux pommes"]}"#).unwrap();
+        assert_eq!(foody.course, Course::Dessert);
+    }
+}
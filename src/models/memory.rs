@@ -0,0 +1,577 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::models::schools::{
+    School, SchoolRepository, SyncReport, SOURCE_IMPORT as SCHOOL_SOURCE_IMPORT, SOURCE_ONISEP,
+};
+#[cfg(test)]
+use crate::{
+    models::{
+        geo::Coordinates,
+        keywords::{
+            merge_keywords, IndexedTerm, KeywordBatch, KeywordExtractor, KeywordRepository, Source,
+            StoredKeyword, Suggestion,
+        },
+        meals::{match_dishes, DishMatch, Meal, MealRepository, ServedMeal},
+        menu_status::{MenuScrapeStatus, MenuStatusRepository},
+        restaurants::{Restaurant, RestaurantRepository, SOURCE_CROUS, SOURCE_IMPORT},
+        search::SearchIndex,
+    },
+    text::{synonyms::Synonyms, tokenizer::Tokenizer},
+};
+#[cfg(test)]
+use std::sync::Arc;
+
+// repositories kept in memory, for the unit tests and the dry runs, which only need the schools.
+// They work with any connection, `()` when there is no database at all, and behave like the
+// Postgres ones except for the suggestions, matched on their prefix and stem only, and for
+// restaurant names missing from them
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryRestaurants {
+    restaurants: Mutex<Vec<Restaurant>>,
+}
+
+#[cfg(test)]
+impl MemoryRestaurants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next_id(restaurants: &[Restaurant]) -> i32 {
+        restaurants
+            .iter()
+            .filter_map(|restaurant| restaurant.idrestaurant)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    fn upsert(&self, restaurants: Vec<Restaurant>, source: &str) -> Vec<(Restaurant, bool)> {
+        let mut stored = self.restaurants.lock().unwrap();
+        let mut upserted = Vec::new();
+        for mut restaurant in restaurants {
+            restaurant.source = source.to_string();
+            let known = stored
+                .iter_mut()
+                .find(|known| known.source == source && known.url == restaurant.url);
+            match known {
+                Some(known) => {
+                    restaurant.idrestaurant = known.idrestaurant;
+                    *known = restaurant.clone();
+                    upserted.push((restaurant, false));
+                }
+                None => {
+                    restaurant.idrestaurant = Some(Self::next_id(&stored));
+                    stored.push(restaurant.clone());
+                    upserted.push((restaurant, true));
+                }
+            }
+        }
+        upserted
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl<C: Send> RestaurantRepository<C> for MemoryRestaurants {
+    async fn find_all(&self, _conn: &mut C) -> Result<Vec<Restaurant>, sqlx::Error> {
+        Ok(self.restaurants.lock().unwrap().clone())
+    }
+
+    async fn create(&self, _conn: &mut C, mut restaurant: Restaurant) -> Result<Restaurant, sqlx::Error> {
+        let mut restaurants = self.restaurants.lock().unwrap();
+        restaurant.idrestaurant = Some(Self::next_id(&restaurants));
+        restaurants.push(restaurant.clone());
+        Ok(restaurant)
+    }
+
+    async fn upsert_scraped(
+        &self,
+        _conn: &mut C,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        Ok(self.upsert(restaurants, SOURCE_CROUS))
+    }
+
+    async fn clear_unlisted(&self, _conn: &mut C, urls: &[String]) -> Result<u64, sqlx::Error> {
+        let mut restaurants = self.restaurants.lock().unwrap();
+        let before = restaurants.len();
+        restaurants.retain(|restaurant| restaurant.source != SOURCE_CROUS || urls.contains(&restaurant.url));
+        Ok((before - restaurants.len()) as u64)
+    }
+
+    async fn import(
+        &self,
+        _conn: &mut C,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        Ok(self.upsert(restaurants, SOURCE_IMPORT))
+    }
+}
+
+#[cfg(test)]
+pub struct MemoryMeals {
+    tokenizer: Tokenizer,
+    synonyms: Arc<Synonyms>,
+    restaurants: Arc<MemoryRestaurants>,
+    meals: Mutex<Vec<Meal>>,
+}
+
+#[cfg(test)]
+impl MemoryMeals {
+    pub fn new(restaurants: Arc<MemoryRestaurants>, synonyms: Arc<Synonyms>) -> Self {
+        Self {
+            tokenizer: Tokenizer::new(),
+            synonyms,
+            restaurants,
+            meals: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl<C: Send> MealRepository<C> for MemoryMeals {
+    async fn create_many(&self, _conn: &mut C, meals: &[Meal]) -> Result<u64, sqlx::Error> {
+        self.meals.lock().unwrap().extend(meals.iter().cloned());
+        Ok(meals.len() as u64)
+    }
+
+    async fn find_all(&self, _conn: &mut C) -> Result<Vec<Meal>, sqlx::Error> {
+        Ok(self.meals.lock().unwrap().clone())
+    }
+
+    async fn find_dish(
+        &self,
+        _conn: &mut C,
+        query: &str,
+        day: chrono::NaiveDate,
+        near: Option<Coordinates>,
+    ) -> Result<Vec<DishMatch>, sqlx::Error> {
+        let restaurants = self.restaurants.restaurants.lock().unwrap().clone();
+        // meals are joined to their restaurant, as the meal table is to the restaurant table
+        let served = self
+            .meals
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|meal| meal.day.date_naive() == day)
+            .filter_map(|meal| {
+                let restaurant = restaurants
+                    .iter()
+                    .find(|restaurant| restaurant.idrestaurant.map(i64::from) == Some(meal.idrestaurant))?;
+                Some(ServedMeal {
+                    meal: meal.clone(),
+                    idrestaurant: restaurant.idrestaurant?,
+                    name: restaurant.name.clone(),
+                    url: restaurant.url.clone(),
+                    coordinates: restaurant.gpscoord.as_deref().and_then(Coordinates::from_point),
+                })
+            })
+            .collect();
+        Ok(match_dishes(&self.tokenizer, &self.synonyms.expand(query), served, near))
+    }
+
+    async fn clean(&self, _conn: &mut C) -> Result<(), sqlx::Error> {
+        self.meals.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+struct MemoryKeyword {
+    display: String,
+    folded: String,
+    stem: String,
+    idrestaurant: i64,
+    source: Source,
+    occurrences: i32,
+    last_seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(test)]
+pub struct MemoryKeywords {
+    extractor: KeywordExtractor,
+    keywords: Mutex<Vec<MemoryKeyword>>,
+    index: Mutex<Vec<IndexedTerm>>,
+}
+
+#[cfg(test)]
+impl MemoryKeywords {
+    pub fn new(extractor: KeywordExtractor) -> Self {
+        Self {
+            extractor,
+            keywords: Mutex::new(Vec::new()),
+            index: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl<C: Send> KeywordRepository<C> for MemoryKeywords {
+    fn extractor(&self) -> &KeywordExtractor {
+        &self.extractor
+    }
+
+    async fn create_many(&self, _conn: &mut C, batch: KeywordBatch) -> Result<u64, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let mut keywords = self.keywords.lock().unwrap();
+        let mut written = 0;
+        for (token, idrestaurant, source, occurrences) in batch.into_rows() {
+            let known = keywords.iter_mut().find(|known| {
+                known.display == token.display
+                    && known.idrestaurant == idrestaurant
+                    && known.source.category() == source.category()
+            });
+            match known {
+                Some(known) => {
+                    known.occurrences += occurrences;
+                    known.last_seen_at = now;
+                    known.folded = token.folded;
+                    known.stem = token.stem;
+                    known.source = source;
+                }
+                None => keywords.push(MemoryKeyword {
+                    display: token.display,
+                    folded: token.folded,
+                    stem: token.stem,
+                    idrestaurant,
+                    source,
+                    occurrences,
+                    last_seen_at: now,
+                }),
+            }
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    async fn gc(
+        &self,
+        _conn: &mut C,
+        older_than: chrono::Duration,
+        supported: &HashSet<(i64, String, Source)>,
+    ) -> Result<u64, sqlx::Error> {
+        let threshold = chrono::Utc::now() - older_than;
+        let mut keywords = self.keywords.lock().unwrap();
+        let before = keywords.len();
+        keywords.retain(|keyword| {
+            keyword.last_seen_at >= threshold
+                || supported.contains(&(keyword.idrestaurant, keyword.display.clone(), keyword.source))
+        });
+        Ok((before - keywords.len()) as u64)
+    }
+
+    async fn refresh_suggestions(&self, _conn: &mut C) -> Result<(), sqlx::Error> {
+        let keywords = self
+            .keywords
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|keyword| StoredKeyword {
+                keyword: keyword.display.clone(),
+                folded: keyword.folded.clone(),
+                stem: keyword.stem.clone(),
+                idrestaurant: keyword.idrestaurant,
+                category: keyword.source.category(),
+                occurrences: keyword.occurrences,
+            })
+            .collect();
+        *self.index.lock().unwrap() = merge_keywords(keywords);
+        Ok(())
+    }
+
+    async fn suggest(
+        &self,
+        _conn: &mut C,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<Suggestion>, sqlx::Error> {
+        let folded = self.extractor.synonyms.expand(prefix);
+        if folded.is_empty() {
+            return Ok(Vec::new());
+        }
+        let stem = self.extractor.tokenizer.stemmer().stem(&folded);
+        let mut suggestions: Vec<Suggestion> = self
+            .index
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|term| term.folded.starts_with(&folded) || term.stem == stem)
+            .map(|term| Suggestion {
+                term: term.term.clone(),
+                category: term.category.name().to_string(),
+                occurrences: term.occurrences,
+                score: 1.0,
+            })
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.occurrences
+                .cmp(&a.occurrences)
+                .then_with(|| a.term.cmp(&b.term))
+        });
+        suggestions.truncate(usize::try_from(limit).unwrap_or(0));
+        Ok(suggestions)
+    }
+}
+
+struct StoredSchool {
+    school: School,
+    source: &'static str,
+    active: bool,
+}
+
+#[derive(Default)]
+pub struct MemorySchools {
+    schools: Mutex<Vec<StoredSchool>>,
+}
+
+impl MemorySchools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn upsert(&self, schools: Vec<School>, source: &'static str) -> SyncReport {
+        let mut stored = self.schools.lock().unwrap();
+        let mut report = SyncReport::default();
+        let mut codes = HashSet::new();
+        for mut school in schools {
+            codes.insert(school.code_uai.clone());
+            match stored.iter_mut().find(|known| known.school.code_uai == school.code_uai) {
+                Some(known) => {
+                    school.idschool = known.school.idschool;
+                    known.school = school;
+                    if source == SOURCE_ONISEP {
+                        known.source = SOURCE_ONISEP;
+                    }
+                    if source == SOURCE_ONISEP || known.source != SOURCE_ONISEP {
+                        known.active = true;
+                    }
+                    report.updated += 1;
+                }
+                None => {
+                    school.idschool = stored.len() as i64 + 1;
+                    stored.push(StoredSchool {
+                        school,
+                        source,
+                        active: true,
+                    });
+                    report.inserted += 1;
+                }
+            }
+        }
+        if source == SOURCE_ONISEP && !codes.is_empty() {
+            for known in stored.iter_mut() {
+                if known.active && known.source == SOURCE_ONISEP && !codes.contains(&known.school.code_uai) {
+                    known.active = false;
+                    report.deactivated += 1;
+                }
+            }
+        }
+        report
+    }
+}
+
+#[async_trait]
+impl<C: Send> SchoolRepository<C> for MemorySchools {
+    async fn sync(&self, _conn: &mut C, schools: Vec<School>) -> Result<SyncReport, sqlx::Error> {
+        Ok(self.upsert(schools, SOURCE_ONISEP))
+    }
+
+    async fn import(&self, _conn: &mut C, schools: Vec<School>) -> Result<SyncReport, sqlx::Error> {
+        Ok(self.upsert(schools, SCHOOL_SOURCE_IMPORT))
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryMenuStatuses {
+    statuses: Mutex<Vec<MenuScrapeStatus>>,
+}
+
+#[cfg(test)]
+impl MemoryMenuStatuses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn find_all(&self) -> Vec<MenuScrapeStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl<C: Send> MenuStatusRepository<C> for MemoryMenuStatuses {
+    async fn create(&self, _conn: &mut C, status: &MenuScrapeStatus) -> Result<(), sqlx::Error> {
+        self.statuses.lock().unwrap().push(status.clone());
+        Ok(())
+    }
+}
+
+// there is no search document in memory, as in SQLite
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySearch {}
+
+#[cfg(test)]
+#[async_trait]
+impl<C: Send> SearchIndex<C> for MemorySearch {
+    async fn refresh(&self, _conn: &mut C) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::actions::meals::Foody,
+        dietary::MealTags,
+        models::meals::{Course, Service},
+    };
+
+    fn restaurant(url: &str, name: &str, source: &str) -> Restaurant {
+        Restaurant {
+            idrestaurant: None,
+            url: url.to_string(),
+            name: name.to_string(),
+            gpscoord: None,
+            hours: None,
+            address: None,
+            geocode_source: None,
+            geocode_confidence: None,
+            source: source.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restaurants_import_and_clear() {
+        let restaurants = MemoryRestaurants::new();
+        let scraped = restaurants
+            .upsert_scraped(
+                &mut (),
+                vec![
+                    restaurant("https://crous/a", "Resto A", SOURCE_CROUS),
+                    restaurant("https://crous/c", "Resto C", SOURCE_CROUS),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(scraped.iter().all(|(_, inserted)| *inserted));
+        let imported = restaurants
+            .import(&mut (), vec![restaurant("https://ecole/b", "Cafet B", SOURCE_IMPORT)])
+            .await
+            .unwrap();
+        assert!(imported[0].1);
+        let imported = restaurants
+            .import(&mut (), vec![restaurant("https://ecole/b", "Cafet B2", SOURCE_IMPORT)])
+            .await
+            .unwrap();
+        assert!(!imported[0].1);
+        assert_eq!(imported[0].0.idrestaurant, Some(3));
+
+        // a new scrape keeps the ids of the restaurants still listed
+        let scraped = restaurants
+            .upsert_scraped(&mut (), vec![restaurant("https://crous/a", "Resto A2", SOURCE_CROUS)])
+            .await
+            .unwrap();
+        assert!(!scraped[0].1);
+        assert_eq!(scraped[0].0.idrestaurant, Some(1));
+        let urls = vec!["https://crous/a".to_string()];
+        let deleted = RestaurantRepository::<()>::clear_unlisted(&restaurants, &mut (), &urls).await.unwrap();
+        assert_eq!(deleted, 1);
+        let left = RestaurantRepository::<()>::find_all(&restaurants, &mut ()).await.unwrap();
+        let names: Vec<&str> = left.iter().map(|restaurant| restaurant.name.as_str()).collect();
+        assert_eq!(names, vec!["Resto A2", "Cafet B2"]);
+    }
+
+    #[tokio::test]
+    async fn test_schools_sync_empty_list() {
+        let schools = MemorySchools::new();
+        let school = School {
+            idschool: 0,
+            code_uai: "0341234A".to_string(),
+            long_name: "Université de Montpellier".to_string(),
+            name: "UM".to_string(),
+            coords: None,
+            address: None,
+            postcode: None,
+            commune: None,
+            phone: None,
+            school_type: None,
+            website: None,
+            geocode_source: None,
+            geocode_confidence: None,
+        };
+        schools.sync(&mut (), vec![school.clone()]).await.unwrap();
+        let report = schools.sync(&mut (), Vec::new()).await.unwrap();
+        assert_eq!(report.deactivated, 0);
+        assert!(schools.schools.lock().unwrap()[0].active);
+
+        // importing it again leaves it to the sync, even once deactivated
+        let other = School {
+            code_uai: "0345678B".to_string(),
+            ..school.clone()
+        };
+        schools.sync(&mut (), vec![other.clone()]).await.unwrap();
+        schools.import(&mut (), vec![school]).await.unwrap();
+        assert_eq!(schools.schools.lock().unwrap()[0].source, SOURCE_ONISEP);
+        assert!(!schools.schools.lock().unwrap()[0].active);
+
+        // a sync takes over an imported school
+        let imported = School {
+            code_uai: "0349999Z".to_string(),
+            ..other.clone()
+        };
+        schools.import(&mut (), vec![imported.clone()]).await.unwrap();
+        schools.sync(&mut (), vec![other, imported]).await.unwrap();
+        assert_eq!(schools.schools.lock().unwrap()[2].source, SOURCE_ONISEP);
+    }
+
+    #[tokio::test]
+    async fn test_find_dish() {
+        let restaurants = Arc::new(MemoryRestaurants::new());
+        let meals = MemoryMeals::new(restaurants.clone(), Arc::new(Synonyms::load().unwrap()));
+        let mut resto = restaurant("https://crous/a", "Resto A", SOURCE_CROUS);
+        resto.gpscoord = Some("(43.63,3.87)".to_string());
+        restaurants.create(&mut (), resto).await.unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        meals
+            .create_many(
+                &mut (),
+                &[Meal {
+                    typemeal: "Déjeuner".to_string(),
+                    service: Service::Lunch,
+                    foodies: sqlx::types::Json(vec![Foody {
+                        r#type: "Plats".to_string(),
+                        course: Course::Main,
+                        content: vec![
+                            "Steak frites".to_string(),
+                            "Poisson pané".to_string(),
+                            "Pommes de terre sautées".to_string(),
+                        ],
+                    }]),
+                    tags: sqlx::types::Json(MealTags::default()),
+                    day: day.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+                    idrestaurant: 1,
+                }],
+            )
+            .await
+            .unwrap();
+
+        let near = Some(Coordinates { lat: 43.6, lon: 3.88 });
+        let found = meals.find_dish(&mut (), "frite", day, near).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].dishes, vec!["Steak frites".to_string()]);
+        assert!(found[0].distance_km.is_some());
+        // "pdt" is an alias of "pommes de terre"
+        let found = meals.find_dish(&mut (), "pdt", day, None).await.unwrap();
+        assert_eq!(found[0].dishes, vec!["Pommes de terre sautées".to_string()]);
+        let tomorrow = day.succ_opt().unwrap();
+        assert!(meals.find_dish(&mut (), "frite", tomorrow, None).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+use sqlx::PgConnection;
+
+#[derive(Clone, Default)]
+pub struct MenuStatusService {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrapeOutcome {
+    Ok,
+    NotPublished,
+    Closed,
+    ParseError,
+    FetchError,
+}
+
+impl ScrapeOutcome {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Ok => "ok",
+            Self::NotPublished => "not_published",
+            Self::Closed => "closed",
+            Self::ParseError => "parse_error",
+            Self::FetchError => "fetch_error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MenuScrapeStatus {
+    pub idrestaurant: i64,
+    pub day: chrono::NaiveDate,
+    pub run_started_at: chrono::DateTime<chrono::Utc>,
+    pub outcome: ScrapeOutcome,
+    pub detail: Option<String>,
+    pub meal_count: i32,
+}
+
+// storage of the menu scrape statuses, through a connection of type C
+#[async_trait]
+pub trait MenuStatusRepository<C: Send>: Send + Sync {
+    async fn create(&self, conn: &mut C, status: &MenuScrapeStatus) -> Result<(), sqlx::Error>;
+}
+
+impl MenuStatusService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl MenuStatusRepository<PgConnection> for MenuStatusService {
+    async fn create(
+        &self,
+        conn: &mut PgConnection,
+        status: &MenuScrapeStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO menu_scrape_status(idrestaurant, day, run_started_at, outcome, detail, meal_count) VALUES ($1, $2, $3, $4, $5, $6)"#,
+        )
+        .bind(status.idrestaurant)
+        .bind(status.day)
+        .bind(status.run_started_at)
+        .bind(status.outcome.as_str())
+        .bind(&status.detail)
+        .bind(status.meal_count)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
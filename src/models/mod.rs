@@ -1,4 +1,13 @@
+pub mod campus;
+pub mod crawl_run;
+pub mod geo;
 pub mod keywords;
 pub mod schools;
 pub mod meals;
+pub mod memory;
+pub mod menu_status;
+pub mod restaurant_school;
 pub mod restaurants;
+pub mod search;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,183 @@
+use std::env;
+
+use sqlx::PgConnection;
+use tracing::warn;
+
+use crate::models::geo::Coordinates;
+
+const DEFAULT_RADIUS_KM: f64 = 2.0;
+const DEFAULT_NEAREST: usize = 3;
+
+// a school is linked to every restaurant within radius_km, and always to its nearest ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkSettings {
+    pub radius_km: f64,
+    pub nearest: usize,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            radius_km: DEFAULT_RADIUS_KM,
+            nearest: DEFAULT_NEAREST,
+        }
+    }
+}
+
+impl LinkSettings {
+    // reads LINK_RADIUS_KM and LINK_NEAREST
+    pub fn from_env() -> Result<Self, String> {
+        let mut settings = Self::default();
+        if let Ok(radius_km) = env::var("LINK_RADIUS_KM") {
+            settings.radius_km = radius_km
+                .trim()
+                .parse()
+                .ok()
+                .filter(|radius_km: &f64| *radius_km >= 0.0)
+                .ok_or(format!("LINK_RADIUS_KM: invalid radius {:?}", radius_km))?;
+        }
+        if let Ok(nearest) = env::var("LINK_NEAREST") {
+            settings.nearest = nearest
+                .trim()
+                .parse()
+                .map_err(|_| format!("LINK_NEAREST: invalid count {:?}", nearest))?;
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestaurantSchool {
+    pub idschool: i32,
+    pub idrestaurant: i32,
+    pub distance_km: f64,
+}
+
+#[derive(Clone)]
+pub struct RestaurantSchoolService {
+    pub settings: LinkSettings,
+}
+
+impl RestaurantSchoolService {
+    pub fn new(settings: LinkSettings) -> Self {
+        Self { settings }
+    }
+
+    // recomputes every link from the current restaurants and active schools, so it can be run
+    // again whenever one of them changes
+    pub async fn rebuild(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<RestaurantSchool>, sqlx::Error> {
+        let schools = located(
+            &mut *conn,
+            "SELECT idschool, coords::text FROM school WHERE coords IS NOT NULL AND active",
+        )
+        .await?;
+        let restaurants = located(
+            &mut *conn,
+            "SELECT idrestaurant, gpscoord::text FROM restaurant WHERE gpscoord IS NOT NULL",
+        )
+        .await?;
+        let links = compute_links(&schools, &restaurants, &self.settings);
+        sqlx::query("DELETE FROM restaurant_school")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            r#"INSERT INTO restaurant_school(idschool, idrestaurant, distance_km)
+            SELECT * FROM UNNEST($1::int[], $2::int[], $3::float8[])"#,
+        )
+        .bind(links.iter().map(|link| link.idschool).collect::<Vec<_>>())
+        .bind(links.iter().map(|link| link.idrestaurant).collect::<Vec<_>>())
+        .bind(links.iter().map(|link| link.distance_km).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+        Ok(links)
+    }
+}
+
+// rows of (id, point), the invalid points are skipped
+pub async fn located(
+    conn: &mut PgConnection,
+    query: &str,
+) -> Result<Vec<(i32, Coordinates)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (i32, String)>(query)
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, point)| match Coordinates::from_point(&point) {
+            Some(coordinates) => Some((id, coordinates)),
+            None => {
+                warn!("invalid point {} for {}", point, id);
+                None
+            }
+        })
+        .collect())
+}
+
+pub fn compute_links(
+    schools: &[(i32, Coordinates)],
+    restaurants: &[(i32, Coordinates)],
+    settings: &LinkSettings,
+) -> Vec<RestaurantSchool> {
+    let mut links = Vec::new();
+    for (idschool, school) in schools.iter() {
+        let mut distances: Vec<(i32, f64)> = restaurants
+            .iter()
+            .map(|(idrestaurant, restaurant)| (*idrestaurant, school.distance_km(restaurant)))
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        links.extend(
+            distances
+                .into_iter()
+                .enumerate()
+                .take_while(|(rank, (_, distance_km))| {
+                    *rank < settings.nearest || *distance_km <= settings.radius_km
+                })
+                .map(|(_, (idrestaurant, distance_km))| RestaurantSchool {
+                    idschool: *idschool,
+                    idrestaurant,
+                    distance_km,
+                }),
+        );
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_links() {
+        let schools = vec![(1, Coordinates { lat: 43.6315, lon: 3.8620 })];
+        let restaurants = vec![
+            (10, Coordinates { lat: 43.6323, lon: 3.8652 }),
+            (11, Coordinates { lat: 43.6330, lon: 3.8610 }),
+            (12, Coordinates { lat: 43.6051, lon: 3.8985 }),
+            (13, Coordinates { lat: 43.4030, lon: 3.6920 }),
+        ];
+        let settings = LinkSettings {
+            radius_km: 1.0,
+            nearest: 1,
+        };
+        let links = compute_links(&schools, &restaurants, &settings);
+        assert_eq!(
+            links.iter().map(|link| link.idrestaurant).collect::<Vec<_>>(),
+            vec![11, 10]
+        );
+
+        // the nearest restaurants are kept even outside the radius
+        let settings = LinkSettings {
+            radius_km: 0.1,
+            nearest: 3,
+        };
+        let links = compute_links(&schools, &restaurants, &settings);
+        assert_eq!(
+            links.iter().map(|link| link.idrestaurant).collect::<Vec<_>>(),
+            vec![11, 10, 12]
+        );
+        assert!(links[2].distance_km > 4.0);
+    }
+}
@@ -1,12 +1,13 @@
-use std::sync::Arc;
-
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, PgPool};
+use sqlx::{prelude::FromRow, PgConnection};
 
-#[derive(Clone)]
-pub struct RestaurantService {
-    pub pool: Arc<PgPool>,
-}
+// restaurants scraped from the CROUS website, the others are imported from a file
+pub const SOURCE_CROUS: &str = "crous";
+pub const SOURCE_IMPORT: &str = "import";
+
+#[derive(Clone, Default)]
+pub struct RestaurantService {}
 
 #[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
 pub struct Restaurant {
@@ -15,54 +16,140 @@ pub struct Restaurant {
     pub name: String,
     pub gpscoord: Option<String>,
     pub hours: Option<String>,
+    pub address: Option<String>,
+    pub geocode_source: Option<String>,
+    pub geocode_confidence: Option<f32>,
+    pub source: String,
+}
+
+// storage of the restaurants, in Postgres or in memory, through a connection of type C
+#[async_trait]
+pub trait RestaurantRepository<C: Send>: Send + Sync {
+    async fn find_all(&self, conn: &mut C) -> Result<Vec<Restaurant>, sqlx::Error>;
+
+    async fn create(&self, conn: &mut C, restaurant: Restaurant) -> Result<Restaurant, sqlx::Error>;
+
+    // upserts scraped restaurants on their url, so they keep their id and what refers to it,
+    // returns them with whether they were inserted
+    async fn upsert_scraped(
+        &self,
+        conn: &mut C,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error>;
+
+    // only the scraped restaurants missing from urls, imported ones are kept, returns how many
+    async fn clear_unlisted(&self, conn: &mut C, urls: &[String]) -> Result<u64, sqlx::Error>;
+
+    // upserts imported restaurants on their url, returns them with whether they were inserted
+    async fn import(
+        &self,
+        conn: &mut C,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error>;
 }
 
 impl RestaurantService {
-    pub fn new(pool: Arc<PgPool>) -> Self {
-        Self { pool }
+    pub fn new() -> Self {
+        Self {}
     }
 
-    #[allow(dead_code)]
-    pub async fn find_all(&self) -> Result<Vec<Restaurant>, sqlx::Error> {
+    // updates the restaurant of the source with the same url, or inserts it
+    async fn upsert(
+        &self,
+        conn: &mut PgConnection,
+        restaurants: Vec<Restaurant>,
+        source: &str,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        let mut upserted = Vec::new();
+        for restaurant in restaurants {
+            let updated = sqlx::query_as::<_, Restaurant>(
+                r#"UPDATE restaurant SET name = $2, gpscoord = $3::point, hours = $4, address = $5, geocode_source = $6, geocode_confidence = $7
+                WHERE source = $8 AND url = $1
+                RETURNING idrestaurant, url, name, gpscoord::text as gpscoord, hours, address, geocode_source, geocode_confidence, source"#,
+            )
+            .bind(&restaurant.url)
+            .bind(&restaurant.name)
+            .bind(&restaurant.gpscoord)
+            .bind(&restaurant.hours)
+            .bind(&restaurant.address)
+            .bind(&restaurant.geocode_source)
+            .bind(restaurant.geocode_confidence)
+            .bind(source)
+            .fetch_optional(&mut *conn)
+            .await?;
+            if let Some(updated) = updated {
+                upserted.push((updated, false));
+                continue;
+            }
+            let inserted = self
+                .create(
+                    &mut *conn,
+                    Restaurant {
+                        source: source.to_string(),
+                        ..restaurant
+                    },
+                )
+                .await?;
+            upserted.push((inserted, true));
+        }
+        Ok(upserted)
+    }
+}
+
+#[async_trait]
+impl RestaurantRepository<PgConnection> for RestaurantService {
+    async fn find_all(&self, conn: &mut PgConnection) -> Result<Vec<Restaurant>, sqlx::Error> {
         let restaurants = sqlx::query_as::<_, Restaurant>(
-            r#"SELECT idrestaurant, url, name, gpscoord::text as gpscoord, hours FROM restaurant"#,
+            r#"SELECT idrestaurant, url, name, gpscoord::text as gpscoord, hours, address, geocode_source, geocode_confidence, source FROM restaurant"#,
         )
-        .fetch_all(self.pool.as_ref())
+        .fetch_all(&mut *conn)
         .await?;
         Ok(restaurants)
     }
 
-    pub async fn create(&self, restaurant: Restaurant) -> Result<Restaurant, sqlx::Error> {
-        if restaurant.gpscoord.is_none() {
-            let restaurant_result = sqlx::query_as::<_, Restaurant>(
-                "INSERT INTO restaurant(url, name, hours) VALUES ($1, $2, $3) RETURNING idrestaurant, url, name, gpscoord::text as gpscoord, hours",
-            )
-            .bind(restaurant.url)
-            .bind(restaurant.name)
-            .bind(restaurant.hours)
-            .fetch_one(self.pool.as_ref())
-            .await?;
-            return Ok(restaurant_result);
-        }
+    async fn create(
+        &self,
+        conn: &mut PgConnection,
+        restaurant: Restaurant,
+    ) -> Result<Restaurant, sqlx::Error> {
         let restaurant_result = sqlx::query_as::<_, Restaurant>(
-            format!(
-                "INSERT INTO restaurant(url, name, hours, gpscoord) VALUES ($1, $2, $3, {}) RETURNING idrestaurant, url, name, gpscoord::text as gpscoord, hours",
-                restaurant.gpscoord.unwrap()
-            )
-            .as_str(),
+            "INSERT INTO restaurant(url, name, hours, address, geocode_source, geocode_confidence, source, gpscoord) VALUES ($1, $2, $3, $4, $5, $6, $7, $8::point) RETURNING idrestaurant, url, name, gpscoord::text as gpscoord, hours, address, geocode_source, geocode_confidence, source",
         )
         .bind(restaurant.url)
         .bind(restaurant.name)
         .bind(restaurant.hours)
-        .fetch_one(self.pool.as_ref())
+        .bind(restaurant.address)
+        .bind(restaurant.geocode_source)
+        .bind(restaurant.geocode_confidence)
+        .bind(restaurant.source)
+        .bind(restaurant.gpscoord)
+        .fetch_one(&mut *conn)
         .await?;
         Ok(restaurant_result)
     }
 
-    pub async fn clear(&self) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM restaurant")
-            .execute(self.pool.as_ref())
+    async fn upsert_scraped(
+        &self,
+        conn: &mut PgConnection,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        self.upsert(conn, restaurants, SOURCE_CROUS).await
+    }
+
+    async fn clear_unlisted(&self, conn: &mut PgConnection, urls: &[String]) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query("DELETE FROM restaurant WHERE source = $1 AND NOT url = ANY($2)")
+            .bind(SOURCE_CROUS)
+            .bind(urls)
+            .execute(&mut *conn)
             .await?;
-        Ok(())
+        Ok(deleted.rows_affected())
+    }
+
+    async fn import(
+        &self,
+        conn: &mut PgConnection,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        self.upsert(conn, restaurants, SOURCE_IMPORT).await
     }
 }
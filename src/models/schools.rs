@@ -1,42 +1,145 @@
-use std::sync::Arc;
-
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, PgPool};
+use sqlx::{prelude::FromRow, PgConnection};
 
-#[derive(Clone)]
-pub struct SchoolService {
-    pub pool: Arc<PgPool>,
-}
+// schools read from an ONISEP dataset, the others are imported from a file
+pub const SOURCE_ONISEP: &str = "onisep";
+pub const SOURCE_IMPORT: &str = "import";
+
+#[derive(Clone, Default)]
+pub struct SchoolService {}
 
 #[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
 pub struct School {
     pub idschool: i64,
+    pub code_uai: String,
     pub long_name: String,
     pub name: String,
-    pub coords: String,
+    pub coords: Option<String>,
+    pub address: Option<String>,
+    pub postcode: Option<String>,
+    pub commune: Option<String>,
+    pub phone: Option<String>,
+    pub school_type: Option<String>,
+    pub website: Option<String>,
+    pub geocode_source: Option<String>,
+    pub geocode_confidence: Option<f32>,
 }
 
-impl SchoolService {
-    pub fn new(pool: Arc<PgPool>) -> Self {
-        Self { pool }
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncReport {
+    pub inserted: u64,
+    pub updated: u64,
+    pub deactivated: u64,
+}
+
+// storage of the schools, in Postgres or in memory, through a connection of type C
+#[async_trait]
+pub trait SchoolRepository<C: Send>: Send + Sync {
+    // upserts every school on its UAI code and deactivates the ONISEP ones missing from the
+    // list, so that users keep their school. An empty list deactivates nothing, it comes from a
+    // failed or empty fetch rather than from every school closing
+    async fn sync(&self, conn: &mut C, schools: Vec<School>) -> Result<SyncReport, sqlx::Error>;
+
+    // upserts imported schools, they are never deactivated by a sync. A school already known
+    // keeps its source, an import doesn't take an ONISEP school out of the sync, nor reactivates
+    // one the sync deactivated. A sync takes over an imported school it lists
+    async fn import(&self, conn: &mut C, schools: Vec<School>) -> Result<SyncReport, sqlx::Error>;
+}
+
+#[async_trait]
+impl SchoolRepository<PgConnection> for SchoolService {
+    async fn sync(
+        &self,
+        conn: &mut PgConnection,
+        schools: Vec<School>,
+    ) -> Result<SyncReport, sqlx::Error> {
+        self.upsert(conn, schools, SOURCE_ONISEP).await
     }
 
-    pub async fn create(&self, school: School) -> Result<(), sqlx::Error> {
-        sqlx::query(format!(
-            "INSERT INTO school(long_name, name, coords) VALUES ($1, $2, '{}')",
-            school.coords.to_string().as_str()
-        ).as_str())
-        .bind(school.long_name)
-        .bind(school.name)
-        .execute(self.pool.as_ref())
-        .await?;
-        Ok(())
+    async fn import(
+        &self,
+        conn: &mut PgConnection,
+        schools: Vec<School>,
+    ) -> Result<SyncReport, sqlx::Error> {
+        self.upsert(conn, schools, SOURCE_IMPORT).await
     }
+}
 
-    pub async fn clear(&self) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM school")
-            .execute(self.pool.as_ref())
+impl SchoolService {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    async fn upsert(
+        &self,
+        conn: &mut PgConnection,
+        schools: Vec<School>,
+        source: &str,
+    ) -> Result<SyncReport, sqlx::Error> {
+        let mut report = SyncReport::default();
+        let mut codes = Vec::new();
+        for school in schools {
+            codes.push(school.code_uai.clone());
+            // schools inserted before UAI codes were stored are adopted by name, unless another
+            // row already has the code
+            sqlx::query(
+                r#"UPDATE school SET code_uai = $1
+                WHERE idschool = (SELECT min(idschool) FROM school WHERE code_uai IS NULL AND long_name = $2)
+                    AND NOT EXISTS (SELECT 1 FROM school WHERE code_uai = $1)"#,
+            )
+            .bind(&school.code_uai)
+            .bind(&school.long_name)
+            .execute(&mut *conn)
+            .await?;
+            let inserted = sqlx::query_scalar::<_, bool>(
+                r#"INSERT INTO school(code_uai, long_name, name, coords, address, postcode, commune, phone, school_type, website, geocode_source, geocode_confidence, source)
+                VALUES ($1, $2, $3, $4::point, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                ON CONFLICT (code_uai) DO UPDATE SET
+                    long_name = EXCLUDED.long_name, name = EXCLUDED.name, coords = EXCLUDED.coords,
+                    address = EXCLUDED.address, postcode = EXCLUDED.postcode, commune = EXCLUDED.commune,
+                    phone = EXCLUDED.phone, school_type = EXCLUDED.school_type, website = EXCLUDED.website,
+                    geocode_source = EXCLUDED.geocode_source, geocode_confidence = EXCLUDED.geocode_confidence,
+                    source = CASE WHEN EXCLUDED.source = $14 THEN $14 ELSE school.source END,
+                    active = CASE WHEN EXCLUDED.source = $14 OR school.source <> $14 THEN true ELSE school.active END,
+                    deactivated_at = CASE WHEN EXCLUDED.source = $14 OR school.source <> $14 THEN NULL ELSE school.deactivated_at END,
+                    updated_at = now()
+                RETURNING xmax = 0"#,
+            )
+            .bind(school.code_uai)
+            .bind(school.long_name)
+            .bind(school.name)
+            .bind(school.coords)
+            .bind(school.address)
+            .bind(school.postcode)
+            .bind(school.commune)
+            .bind(school.phone)
+            .bind(school.school_type)
+            .bind(school.website)
+            .bind(school.geocode_source)
+            .bind(school.geocode_confidence)
+            .bind(source)
+            .bind(SOURCE_ONISEP)
+            .fetch_one(&mut *conn)
             .await?;
-        Ok(())
+            if inserted {
+                report.inserted += 1;
+            } else {
+                report.updated += 1;
+            }
+        }
+
+        if source == SOURCE_ONISEP && !codes.is_empty() {
+            report.deactivated = sqlx::query(
+                r#"UPDATE school SET active = false, deactivated_at = now()
+                WHERE active AND source = $2 AND (code_uai IS NULL OR NOT code_uai = ANY($1))"#,
+            )
+            .bind(codes)
+            .bind(SOURCE_ONISEP)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+        }
+        Ok(report)
     }
 }
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::{prelude::FromRow, PgConnection};
+
+use crate::text::{
+    stopwords::is_search_stopword,
+    synonyms::Synonyms,
+    tokenizer::{Token, Tokenizer},
+};
+
+#[derive(Clone)]
+pub struct SearchService {
+    pub tokenizer: Tokenizer,
+    pub synonyms: Arc<Synonyms>,
+}
+
+#[derive(Debug, FromRow, Clone)]
+pub struct SearchHit {
+    pub idrestaurant: i32,
+    pub name: String,
+    pub url: String,
+    pub address: Option<String>,
+    pub rank: f32,
+}
+
+// the index restaurants are searched on, refreshed once restaurants or meals changed
+#[async_trait]
+pub trait SearchIndex<C: Send>: Send + Sync {
+    async fn refresh(&self, conn: &mut C) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+impl SearchIndex<PgConnection> for SearchService {
+    // rebuilds the search document of every restaurant from its name (A), its address (B) and
+    // the dishes it currently serves (C), already indexed on each meal
+    async fn refresh(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM restaurant_search")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            r#"INSERT INTO restaurant_search(idrestaurant, document)
+            SELECT r.idrestaurant,
+                setweight(to_tsvector('public.french_unaccent', coalesce(r.name, '')), 'A')
+                || setweight(to_tsvector('public.french_unaccent', coalesce(r.address, '')), 'B')
+                || setweight(coalesce((
+                    SELECT tsvector_agg(m.search) FROM meal m WHERE m.idrestaurant = r.idrestaurant
+                ), ''::tsvector), 'C')
+            FROM restaurant r"#,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+impl SearchService {
+    pub fn new(synonyms: Arc<Synonyms>) -> Self {
+        Self {
+            tokenizer: Tokenizer::new(),
+            synonyms,
+        }
+    }
+
+    pub async fn search(
+        &self,
+        conn: &mut PgConnection,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, sqlx::Error> {
+        let tsquery = match prefix_tsquery(&self.tokenizer, &self.synonyms.expand(query)) {
+            Some(tsquery) => tsquery,
+            None => return Ok(Vec::new()),
+        };
+        let hits = sqlx::query_as::<_, SearchHit>(
+            r#"SELECT r.idrestaurant, r.name, r.url, r.address, ts_rank_cd(s.document, q) AS rank
+            FROM restaurant_search s
+            JOIN restaurant r ON r.idrestaurant = s.idrestaurant,
+            to_tsquery('simple', $1) q
+            WHERE s.document @@ q
+            ORDER BY rank DESC, r.name
+            LIMIT $2"#,
+        )
+        .bind(tsquery)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(hits)
+    }
+}
+
+// every word of the query has to match, as a prefix of its stem, so "végé" finds "végétarien"
+// and "carottes" finds "carotte". The terms are already stemmed, so they go through the 'simple'
+// configuration, french_unaccent would stem them a second time
+pub fn prefix_tsquery(tokenizer: &Tokenizer, query: &str) -> Option<String> {
+    let terms = query_tokens(tokenizer, query)
+        .into_iter()
+        .map(|token| format!("'{}':*", token.stem.replace('\'', "''")))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(terms.join(" & "))
+}
+
+// the words of a search query, without those only saying where to look
+pub fn query_tokens(tokenizer: &Tokenizer, query: &str) -> Vec<Token> {
+    tokenizer
+        .tokenize(query)
+        .into_iter()
+        .filter(|token| !is_search_stopword(&token.folded))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_tsquery() {
+        let tokenizer = Tokenizer::new();
+        assert_eq!(
+            prefix_tsquery(&tokenizer, "lasagnes végé près de Triolet"),
+            Some("'lasagn':* & 'veg':* & 'triolet':*".to_string())
+        );
+        assert_eq!(prefix_tsquery(&tokenizer, "de la"), None);
+    }
+}
@@ -0,0 +1,830 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use sqlx::{prelude::FromRow, Connection, QueryBuilder, Sqlite, SqliteConnection};
+
+use crate::{
+    cli::actions::meals::Foody,
+    dietary::MealTags,
+    models::{
+        crawl_run::{CrawlRun, CrawlRunRepository, CrawlRunRow, CrawlRunService, CRAWL_RUN_COLUMNS},
+        geo::Coordinates,
+        keywords::{
+            merge_keywords, Category, IndexedTerm, KeywordBatch, KeywordExtractor, KeywordRepository,
+            KeywordService, Source, StoredKeyword, Suggestion,
+        },
+        meals::{match_dishes, DishMatch, Meal, MealRepository, MealService, ServedMeal, Service},
+        menu_status::{MenuScrapeStatus, MenuStatusRepository, MenuStatusService},
+        restaurants::{Restaurant, RestaurantRepository, RestaurantService, SOURCE_CROUS, SOURCE_IMPORT},
+        schools::{School, SchoolRepository, SchoolService, SyncReport, SOURCE_IMPORT as SCHOOL_SOURCE_IMPORT, SOURCE_ONISEP},
+        search::{SearchIndex, SearchService},
+    },
+};
+
+// rows per multi-row insert, well under the bound parameters SQLite accepts in one statement
+const CHUNK_SIZE: usize = 100;
+
+const RESTAURANT_COLUMNS: &str = "idrestaurant, url, coalesce(name, '') AS name, lat, lon, hours, address, geocode_source, geocode_confidence, source";
+
+// restaurant as stored, with its coordinates in two columns instead of a point
+#[derive(FromRow)]
+struct RestaurantRow {
+    idrestaurant: i64,
+    url: String,
+    name: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    hours: Option<String>,
+    address: Option<String>,
+    geocode_source: Option<String>,
+    geocode_confidence: Option<f64>,
+    source: String,
+}
+
+impl From<RestaurantRow> for Restaurant {
+    fn from(row: RestaurantRow) -> Self {
+        Self {
+            idrestaurant: i32::try_from(row.idrestaurant).ok(),
+            url: row.url,
+            name: row.name,
+            gpscoord: row.lat.zip(row.lon).map(|(lat, lon)| format!("({},{})", lat, lon)),
+            hours: row.hours,
+            address: row.address,
+            geocode_source: row.geocode_source,
+            geocode_confidence: row.geocode_confidence.map(|confidence| confidence as f32),
+            source: row.source,
+        }
+    }
+}
+
+// "(lat,lon)" points of the models, split into lat and lon
+fn split_point(point: Option<&str>) -> (Option<f64>, Option<f64>) {
+    match point.and_then(Coordinates::from_point) {
+        Some(coordinates) => (Some(coordinates.lat), Some(coordinates.lon)),
+        None => (None, None),
+    }
+}
+
+#[async_trait]
+impl RestaurantRepository<SqliteConnection> for RestaurantService {
+    async fn find_all(&self, conn: &mut SqliteConnection) -> Result<Vec<Restaurant>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, RestaurantRow>(&format!("SELECT {} FROM restaurant", RESTAURANT_COLUMNS))
+            .fetch_all(&mut *conn)
+            .await?;
+        Ok(rows.into_iter().map(Restaurant::from).collect())
+    }
+
+    async fn create(
+        &self,
+        conn: &mut SqliteConnection,
+        restaurant: Restaurant,
+    ) -> Result<Restaurant, sqlx::Error> {
+        let (lat, lon) = split_point(restaurant.gpscoord.as_deref());
+        let row = sqlx::query_as::<_, RestaurantRow>(&format!(
+            "INSERT INTO restaurant(url, name, lat, lon, hours, address, geocode_source, geocode_confidence, source) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+            RESTAURANT_COLUMNS
+        ))
+        .bind(restaurant.url)
+        .bind(restaurant.name)
+        .bind(lat)
+        .bind(lon)
+        .bind(restaurant.hours)
+        .bind(restaurant.address)
+        .bind(restaurant.geocode_source)
+        .bind(restaurant.geocode_confidence.map(f64::from))
+        .bind(restaurant.source)
+        .fetch_one(&mut *conn)
+        .await?;
+        Ok(Restaurant::from(row))
+    }
+
+    async fn upsert_scraped(
+        &self,
+        conn: &mut SqliteConnection,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        upsert_restaurants(self, conn, restaurants, SOURCE_CROUS).await
+    }
+
+    async fn clear_unlisted(&self, conn: &mut SqliteConnection, urls: &[String]) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query("DELETE FROM restaurant WHERE source = ? AND url NOT IN (SELECT value FROM json_each(?))")
+            .bind(SOURCE_CROUS)
+            .bind(sqlx::types::Json(urls))
+            .execute(&mut *conn)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+
+    async fn import(
+        &self,
+        conn: &mut SqliteConnection,
+        restaurants: Vec<Restaurant>,
+    ) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+        upsert_restaurants(self, conn, restaurants, SOURCE_IMPORT).await
+    }
+}
+
+// updates the restaurant of the source with the same url, or inserts it
+async fn upsert_restaurants(
+    service: &RestaurantService,
+    conn: &mut SqliteConnection,
+    restaurants: Vec<Restaurant>,
+    source: &str,
+) -> Result<Vec<(Restaurant, bool)>, sqlx::Error> {
+    let mut upserted = Vec::new();
+    for restaurant in restaurants {
+        let (lat, lon) = split_point(restaurant.gpscoord.as_deref());
+        let updated = sqlx::query_as::<_, RestaurantRow>(&format!(
+            r#"UPDATE restaurant SET name = ?, lat = ?, lon = ?, hours = ?, address = ?, geocode_source = ?, geocode_confidence = ?
+            WHERE source = ? AND url = ?
+            RETURNING {}"#,
+            RESTAURANT_COLUMNS
+        ))
+        .bind(&restaurant.name)
+        .bind(lat)
+        .bind(lon)
+        .bind(&restaurant.hours)
+        .bind(&restaurant.address)
+        .bind(&restaurant.geocode_source)
+        .bind(restaurant.geocode_confidence.map(f64::from))
+        .bind(source)
+        .bind(&restaurant.url)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some(updated) = updated {
+            upserted.push((Restaurant::from(updated), false));
+            continue;
+        }
+        let inserted = service
+            .create(
+                &mut *conn,
+                Restaurant {
+                    source: source.to_string(),
+                    ..restaurant
+                },
+            )
+            .await?;
+        upserted.push((inserted, true));
+    }
+    Ok(upserted)
+}
+
+// meal as stored, JSON columns are TEXT
+#[derive(FromRow)]
+struct MealRow {
+    typemeal: Option<String>,
+    service: Option<String>,
+    foodies: Option<sqlx::types::Json<Vec<Foody>>>,
+    tags: Option<sqlx::types::Json<MealTags>>,
+    day: chrono::NaiveDate,
+    idrestaurant: i64,
+}
+
+impl From<MealRow> for Meal {
+    fn from(row: MealRow) -> Self {
+        Self {
+            typemeal: row.typemeal.unwrap_or_default(),
+            service: Service::from(row.service.unwrap_or_default()),
+            foodies: row.foodies.unwrap_or_default(),
+            tags: row.tags.unwrap_or_default(),
+            day: row.day.and_time(chrono::NaiveTime::MIN).and_utc(),
+            idrestaurant: row.idrestaurant,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct ServedMealRow {
+    #[sqlx(flatten)]
+    meal: MealRow,
+    name: String,
+    url: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+}
+
+#[async_trait]
+impl MealRepository<SqliteConnection> for MealService {
+    // multi-row inserts, in a savepoint so that a failing chunk leaves no meal behind
+    async fn create_many(&self, conn: &mut SqliteConnection, meals: &[Meal]) -> Result<u64, sqlx::Error> {
+        if meals.is_empty() {
+            return Ok(0);
+        }
+        let mut savepoint = conn.begin().await?;
+        let mut inserted = 0;
+        for chunk in meals.chunks(CHUNK_SIZE) {
+            let mut query: QueryBuilder<Sqlite> =
+                QueryBuilder::new("INSERT INTO meal(typemeal, service, foodies, tags, day, idrestaurant) ");
+            query.push_values(chunk, |mut values, meal| {
+                values
+                    .push_bind(meal.typemeal.clone())
+                    .push_bind(meal.service.as_str().to_string())
+                    .push_bind(meal.foodies.clone())
+                    .push_bind(meal.tags.clone())
+                    .push_bind(meal.day.date_naive())
+                    .push_bind(meal.idrestaurant);
+            });
+            inserted += query.build().execute(&mut *savepoint).await?.rows_affected();
+        }
+        savepoint.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn find_all(&self, conn: &mut SqliteConnection) -> Result<Vec<Meal>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MealRow>(
+            r#"SELECT typemeal, service, foodies, tags, day, idrestaurant
+            FROM meal
+            WHERE day IS NOT NULL AND idrestaurant IS NOT NULL"#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(rows.into_iter().map(Meal::from).collect())
+    }
+
+    // without a full text search, the dishes of the day are matched on their stems in Rust
+    async fn find_dish(
+        &self,
+        conn: &mut SqliteConnection,
+        query: &str,
+        day: chrono::NaiveDate,
+        near: Option<Coordinates>,
+    ) -> Result<Vec<DishMatch>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, ServedMealRow>(
+            r#"SELECT m.typemeal, m.service, m.foodies, m.tags, m.day, m.idrestaurant,
+                coalesce(r.name, '') AS name, r.url, r.lat, r.lon
+            FROM meal m
+            JOIN restaurant r ON r.idrestaurant = m.idrestaurant
+            WHERE m.day = ?"#,
+        )
+        .bind(day)
+        .fetch_all(&mut *conn)
+        .await?;
+        let served = rows
+            .into_iter()
+            .map(|row| ServedMeal {
+                idrestaurant: i32::try_from(row.meal.idrestaurant).unwrap_or_default(),
+                name: row.name,
+                url: row.url,
+                coordinates: row.lat.zip(row.lon).map(|(lat, lon)| Coordinates { lat, lon }),
+                meal: Meal::from(row.meal),
+            })
+            .collect();
+        Ok(match_dishes(&self.tokenizer, &self.synonyms.expand(query), served, near))
+    }
+
+    async fn clean(&self, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM meal")
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(FromRow)]
+struct KeywordRow {
+    idsuggestion: i64,
+    keyword: String,
+    idrestaurant: i64,
+    source: Option<String>,
+    last_seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(FromRow)]
+struct StoredKeywordRow {
+    keyword: String,
+    folded: String,
+    stem: String,
+    idrestaurant: i64,
+    category: String,
+    occurrences: i32,
+}
+
+// categories are stored by name, there is no cat_suggestions table to sync
+#[async_trait]
+impl KeywordRepository<SqliteConnection> for KeywordService {
+    fn extractor(&self) -> &KeywordExtractor {
+        self.extractor()
+    }
+
+    async fn create_many(
+        &self,
+        conn: &mut SqliteConnection,
+        batch: KeywordBatch,
+    ) -> Result<u64, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let rows: Vec<_> = batch.into_rows().collect();
+        let mut written = 0;
+        for chunk in rows.chunks(CHUNK_SIZE) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO suggestions_restaurant(keyword, folded, stem, idrestaurant, category, source, occurrences, last_seen_at) ",
+            );
+            query.push_values(chunk, |mut values, (token, idrestaurant, source, occurrences)| {
+                values
+                    .push_bind(token.display.clone())
+                    .push_bind(token.folded.clone())
+                    .push_bind(token.stem.clone())
+                    .push_bind(*idrestaurant)
+                    .push_bind(source.category().name().to_string())
+                    .push_bind(source.as_str().to_string())
+                    .push_bind(*occurrences)
+                    .push_bind(now);
+            });
+            query.push(
+                r#" ON CONFLICT (keyword, idrestaurant, category)
+                DO UPDATE SET occurrences = occurrences + excluded.occurrences, last_seen_at = excluded.last_seen_at, folded = excluded.folded, stem = excluded.stem, source = excluded.source"#,
+            );
+            written += query.build().execute(&mut *conn).await?.rows_affected();
+        }
+        Ok(written)
+    }
+
+    async fn gc(
+        &self,
+        conn: &mut SqliteConnection,
+        older_than: chrono::Duration,
+        supported: &HashSet<(i64, String, Source)>,
+    ) -> Result<u64, sqlx::Error> {
+        let threshold = chrono::Utc::now() - older_than;
+        let keywords = sqlx::query_as::<_, KeywordRow>(
+            "SELECT idsuggestion, keyword, idrestaurant, source, last_seen_at FROM suggestions_restaurant",
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let stale: Vec<i64> = keywords
+            .into_iter()
+            .filter(|keyword| keyword.last_seen_at < threshold)
+            .filter(|keyword| match keyword.source.as_deref().and_then(Source::from_name) {
+                Some(source) => !supported.contains(&(keyword.idrestaurant, keyword.keyword.clone(), source)),
+                None => true,
+            })
+            .map(|keyword| keyword.idsuggestion)
+            .collect();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+
+        let deleted = sqlx::query("DELETE FROM suggestions_restaurant WHERE idsuggestion IN (SELECT value FROM json_each(?))")
+            .bind(sqlx::types::Json(stale))
+            .execute(&mut *conn)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+
+    // merged in Rust, with the full name of each restaurant as in Postgres
+    async fn refresh_suggestions(&self, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+        let keywords = sqlx::query_as::<_, StoredKeywordRow>(
+            r#"SELECT keyword, folded, coalesce(stem, folded) AS stem, idrestaurant, category, occurrences
+            FROM suggestions_restaurant
+            WHERE folded IS NOT NULL"#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let keywords = keywords
+            .into_iter()
+            .filter_map(|row| {
+                Some(StoredKeyword {
+                    category: Category::ALL.into_iter().find(|category| category.name() == row.category)?,
+                    keyword: row.keyword,
+                    folded: row.folded,
+                    stem: row.stem,
+                    idrestaurant: row.idrestaurant,
+                    occurrences: row.occurrences,
+                })
+            })
+            .collect();
+        let mut terms = merge_keywords(keywords);
+
+        let names = sqlx::query_scalar::<_, String>("SELECT name FROM restaurant WHERE name IS NOT NULL")
+            .fetch_all(&mut *conn)
+            .await?;
+        let mut restaurants: HashMap<String, IndexedTerm> = HashMap::new();
+        for name in names {
+            let token = self.extractor().term_token(&name);
+            let term = restaurants.entry(token.folded.clone()).or_insert_with(|| IndexedTerm {
+                term: name.clone(),
+                folded: token.folded,
+                stem: token.stem,
+                category: Category::Restaurant,
+                occurrences: 0,
+                restaurants: 0,
+            });
+            term.term = term.term.clone().min(name);
+            term.occurrences += 1;
+            term.restaurants += 1;
+        }
+        terms.extend(restaurants.into_values());
+
+        sqlx::query("DELETE FROM suggestion_index")
+            .execute(&mut *conn)
+            .await?;
+        for chunk in terms.chunks(CHUNK_SIZE) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT OR IGNORE INTO suggestion_index(term, folded, stem, category, occurrences, restaurants) ",
+            );
+            query.push_values(chunk, |mut values, term| {
+                values
+                    .push_bind(term.term.clone())
+                    .push_bind(term.folded.clone())
+                    .push_bind(term.stem.clone())
+                    .push_bind(term.category.name().to_string())
+                    .push_bind(term.occurrences)
+                    .push_bind(term.restaurants);
+            });
+            query.build().execute(&mut *conn).await?;
+        }
+        Ok(())
+    }
+
+    // no trigram similarity in SQLite: terms starting with the prefix or sharing its stem only
+    async fn suggest(
+        &self,
+        conn: &mut SqliteConnection,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<Suggestion>, sqlx::Error> {
+        let extractor = self.extractor();
+        let folded = extractor.synonyms.expand(prefix);
+        if folded.is_empty() {
+            return Ok(Vec::new());
+        }
+        let stem = extractor.tokenizer.stemmer().stem(&folded);
+        let pattern = format!(
+            "{}%",
+            folded.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        let suggestions = sqlx::query_as::<_, Suggestion>(
+            r#"SELECT term, category, occurrences, CAST(1.0 AS REAL) AS score
+            FROM suggestion_index
+            WHERE folded LIKE ? ESCAPE '\' OR stem = ?
+            ORDER BY occurrences DESC, term
+            LIMIT ?"#,
+        )
+        .bind(pattern)
+        .bind(stem)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(suggestions)
+    }
+}
+
+#[async_trait]
+impl SchoolRepository<SqliteConnection> for SchoolService {
+    async fn sync(
+        &self,
+        conn: &mut SqliteConnection,
+        schools: Vec<School>,
+    ) -> Result<SyncReport, sqlx::Error> {
+        upsert_schools(conn, schools, SOURCE_ONISEP).await
+    }
+
+    async fn import(
+        &self,
+        conn: &mut SqliteConnection,
+        schools: Vec<School>,
+    ) -> Result<SyncReport, sqlx::Error> {
+        upsert_schools(conn, schools, SCHOOL_SOURCE_IMPORT).await
+    }
+}
+
+async fn upsert_schools(
+    conn: &mut SqliteConnection,
+    schools: Vec<School>,
+    source: &str,
+) -> Result<SyncReport, sqlx::Error> {
+    let mut report = SyncReport::default();
+    let mut codes = Vec::new();
+    for school in schools {
+        codes.push(school.code_uai.clone());
+        // schools inserted before UAI codes were stored are adopted by name, unless another row
+        // already has the code
+        sqlx::query(
+            r#"UPDATE school SET code_uai = ?1
+            WHERE idschool = (SELECT min(idschool) FROM school WHERE code_uai IS NULL AND long_name = ?2)
+                AND NOT EXISTS (SELECT 1 FROM school WHERE code_uai = ?1)"#,
+        )
+        .bind(&school.code_uai)
+        .bind(&school.long_name)
+        .execute(&mut *conn)
+        .await?;
+        let known = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM school WHERE code_uai = ?")
+            .bind(&school.code_uai)
+            .fetch_one(&mut *conn)
+            .await?;
+        let (lat, lon) = split_point(school.coords.as_deref());
+        sqlx::query(
+            r#"INSERT INTO school(code_uai, long_name, name, lat, lon, address, postcode, commune, phone, school_type, website, geocode_source, geocode_confidence, source)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (code_uai) DO UPDATE SET
+                long_name = excluded.long_name, name = excluded.name, lat = excluded.lat, lon = excluded.lon,
+                address = excluded.address, postcode = excluded.postcode, commune = excluded.commune,
+                phone = excluded.phone, school_type = excluded.school_type, website = excluded.website,
+                geocode_source = excluded.geocode_source, geocode_confidence = excluded.geocode_confidence,
+                source = CASE WHEN excluded.source = ?15 THEN ?15 ELSE school.source END,
+                active = CASE WHEN excluded.source = ?15 OR school.source <> ?15 THEN 1 ELSE school.active END,
+                deactivated_at = CASE WHEN excluded.source = ?15 OR school.source <> ?15 THEN NULL ELSE school.deactivated_at END,
+                updated_at = CURRENT_TIMESTAMP"#,
+        )
+        .bind(school.code_uai)
+        .bind(school.long_name)
+        .bind(school.name)
+        .bind(lat)
+        .bind(lon)
+        .bind(school.address)
+        .bind(school.postcode)
+        .bind(school.commune)
+        .bind(school.phone)
+        .bind(school.school_type)
+        .bind(school.website)
+        .bind(school.geocode_source)
+        .bind(school.geocode_confidence.map(f64::from))
+        .bind(source)
+        .bind(SOURCE_ONISEP)
+        .execute(&mut *conn)
+        .await?;
+        if known == 0 {
+            report.inserted += 1;
+        } else {
+            report.updated += 1;
+        }
+    }
+
+    if source == SOURCE_ONISEP && !codes.is_empty() {
+        report.deactivated = sqlx::query(
+            r#"UPDATE school SET active = 0, deactivated_at = CURRENT_TIMESTAMP
+            WHERE active AND source = ? AND (code_uai IS NULL OR code_uai NOT IN (SELECT value FROM json_each(?)))"#,
+        )
+        .bind(SOURCE_ONISEP)
+        .bind(sqlx::types::Json(codes))
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    }
+    Ok(report)
+}
+
+#[async_trait]
+impl MenuStatusRepository<SqliteConnection> for MenuStatusService {
+    async fn create(
+        &self,
+        conn: &mut SqliteConnection,
+        status: &MenuScrapeStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO menu_scrape_status(idrestaurant, day, run_started_at, outcome, detail, meal_count) VALUES (?, ?, ?, ?, ?, ?)"#,
+        )
+        .bind(status.idrestaurant)
+        .bind(status.day)
+        .bind(status.run_started_at)
+        .bind(status.outcome.as_str())
+        .bind(&status.detail)
+        .bind(status.meal_count)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CrawlRunRepository<SqliteConnection> for CrawlRunService {
+    // runs are written outside of any transaction, where a RETURNING statement left unfinished
+    // wouldn't commit
+    async fn create(&self, conn: &mut SqliteConnection, run: &CrawlRun) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"INSERT INTO crawl_run(action, region, started_at, ended_at, status, fetched, parsed, inserted, updated, skipped, errors, message)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+        )
+        .bind(&run.action)
+        .bind(&run.region)
+        .bind(run.started_at)
+        .bind(run.ended_at)
+        .bind(run.status.as_str())
+        .bind(run.counts.fetched)
+        .bind(run.counts.parsed)
+        .bind(run.counts.inserted)
+        .bind(run.counts.updated)
+        .bind(run.counts.skipped)
+        .bind(run.counts.errors)
+        .bind(&run.message)
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    // dates are TEXT, the latest runs are the last inserted
+    async fn find_all(
+        &self,
+        conn: &mut SqliteConnection,
+        action: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<CrawlRun>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, CrawlRunRow>(&format!(
+            r#"SELECT {} FROM crawl_run
+            WHERE ?1 IS NULL OR action = ?1
+            ORDER BY idrun DESC
+            LIMIT ?2"#,
+            CRAWL_RUN_COLUMNS
+        ))
+        .bind(action)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(rows.into_iter().map(CrawlRun::from).collect())
+    }
+
+    async fn find(&self, conn: &mut SqliteConnection, idrun: i64) -> Result<Option<CrawlRun>, sqlx::Error> {
+        let row = sqlx::query_as::<_, CrawlRunRow>(&format!(
+            "SELECT {} FROM crawl_run WHERE idrun = ?",
+            CRAWL_RUN_COLUMNS
+        ))
+        .bind(idrun)
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(row.map(CrawlRun::from))
+    }
+}
+
+// SQLite has no full text index to refresh, restaurants are only searched on Postgres
+#[async_trait]
+impl SearchIndex<SqliteConnection> for SearchService {
+    async fn refresh(&self, _conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        dietary::MealTags,
+        models::{
+            crawl_run::{RunCounts, RunStatus},
+            meals::Course,
+        },
+        text::synonyms::Synonyms,
+    };
+
+    #[tokio::test]
+    async fn test_find_dish() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations-sqlite/").run_direct(&mut conn).await.unwrap();
+        let restaurant = RestaurantService::new()
+            .create(
+                &mut conn,
+                Restaurant {
+                    idrestaurant: None,
+                    url: "https://crous/a".to_string(),
+                    name: "Resto A".to_string(),
+                    gpscoord: Some("(43.63,3.87)".to_string()),
+                    hours: None,
+                    address: None,
+                    geocode_source: None,
+                    geocode_confidence: None,
+                    source: SOURCE_CROUS.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(restaurant.gpscoord.as_deref(), Some("(43.63,3.87)"));
+
+        let meals = MealService::new(Arc::new(Synonyms::load().unwrap()));
+        let day = chrono::NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        meals
+            .create_many(
+                &mut conn,
+                &[Meal {
+                    typemeal: "Déjeuner".to_string(),
+                    service: Service::Lunch,
+                    foodies: sqlx::types::Json(vec![Foody {
+                        r#type: "Plats".to_string(),
+                        course: Course::Main,
+                        content: vec!["Steak frites".to_string(), "Poisson pané".to_string()],
+                    }]),
+                    tags: sqlx::types::Json(MealTags::default()),
+                    day: day.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+                    idrestaurant: i64::from(restaurant.idrestaurant.unwrap()),
+                }],
+            )
+            .await
+            .unwrap();
+
+        let near = Some(Coordinates { lat: 43.6, lon: 3.88 });
+        let found = meals.find_dish(&mut conn, "frite", day, near).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].dishes, vec!["Steak frites".to_string()]);
+        assert!(found[0].distance_km.is_some());
+        let tomorrow = day.succ_opt().unwrap();
+        assert!(meals.find_dish(&mut conn, "frite", tomorrow, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_crawl_runs() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations-sqlite/").run_direct(&mut conn).await.unwrap();
+        let runs = CrawlRunService::new();
+        let run = |action: &str, status: RunStatus| CrawlRun {
+            idrun: 0,
+            action: action.to_string(),
+            region: "montpellier".to_string(),
+            started_at: chrono::Utc::now(),
+            ended_at: chrono::Utc::now(),
+            status,
+            counts: RunCounts {
+                fetched: 3,
+                parsed: 2,
+                errors: 1,
+                ..RunCounts::default()
+            },
+            message: "meals done".to_string(),
+        };
+        let first = runs.create(&mut conn, &run("meals", RunStatus::Succeeded)).await.unwrap();
+        runs.create(&mut conn, &run("restaurant", RunStatus::Failed)).await.unwrap();
+        let last = runs.create(&mut conn, &run("meals", RunStatus::Failed)).await.unwrap();
+
+        let meals = runs.find_all(&mut conn, Some("meals"), 10).await.unwrap();
+        assert_eq!(meals.iter().map(|run| run.idrun).collect::<Vec<_>>(), vec![last, first]);
+        assert_eq!(runs.find_all(&mut conn, None, 10).await.unwrap().len(), 3);
+
+        let found = runs.find(&mut conn, first).await.unwrap().unwrap();
+        assert_eq!(found.status, RunStatus::Succeeded);
+        assert_eq!(found.counts.errors, 1);
+        assert_eq!(found.message, "meals done");
+        assert!(runs.find(&mut conn, 42).await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "stemming")]
+    #[tokio::test]
+    async fn test_suggest_restaurant_names() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations-sqlite/").run_direct(&mut conn).await.unwrap();
+        RestaurantService::new()
+            .create(
+                &mut conn,
+                Restaurant {
+                    idrestaurant: None,
+                    url: "https://crous/brasserie".to_string(),
+                    name: "Brasserie".to_string(),
+                    gpscoord: None,
+                    hours: None,
+                    address: None,
+                    geocode_source: None,
+                    geocode_confidence: None,
+                    source: SOURCE_CROUS.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let keywords = KeywordService::new(Arc::new(Synonyms::load().unwrap()));
+        keywords.refresh_suggestions(&mut conn).await.unwrap();
+
+        // the name is stemmed as the query is, so the plural finds it
+        let suggestions = keywords.suggest(&mut conn, "brasseries", 5).await.unwrap();
+        assert_eq!(
+            suggestions.iter().map(|suggestion| suggestion.term.as_str()).collect::<Vec<_>>(),
+            vec!["Brasserie"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_school_sources() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations-sqlite/").run_direct(&mut conn).await.unwrap();
+        let school = |code_uai: &str| School {
+            idschool: 0,
+            code_uai: code_uai.to_string(),
+            long_name: format!("École {}", code_uai),
+            name: code_uai.to_string(),
+            coords: None,
+            address: None,
+            postcode: None,
+            commune: None,
+            phone: None,
+            school_type: None,
+            website: None,
+            geocode_source: None,
+            geocode_confidence: None,
+        };
+        let schools = SchoolService::new();
+        schools.import(&mut conn, vec![school("0341234A")]).await.unwrap();
+        schools.sync(&mut conn, vec![school("0341234A"), school("0345678B")]).await.unwrap();
+        // the sync took over the imported school, then deactivates the one it no longer lists
+        schools.sync(&mut conn, vec![school("0341234A")]).await.unwrap();
+        // which an import doesn't bring back
+        schools.import(&mut conn, vec![school("0345678B")]).await.unwrap();
+
+        let rows = sqlx::query_as::<_, (String, String, bool)>("SELECT code_uai, source, active FROM school ORDER BY code_uai")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("0341234A".to_string(), SOURCE_ONISEP.to_string(), true),
+                ("0345678B".to_string(), SOURCE_ONISEP.to_string(), false),
+            ]
+        );
+    }
+}
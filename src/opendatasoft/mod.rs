@@ -0,0 +1,254 @@
+use std::{fmt::Display, time::Duration};
+
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::{info, warn};
+use url::Url;
+
+// Client for the OpenDataSoft Explore API v2.1, used by the open data portals we import from.
+// Documentation here : https://help.opendatasoft.com/apis/ods-explore-v2/
+
+// maximum page size of the records endpoint
+const PAGE_SIZE: usize = 100;
+// the records endpoint refuses offset + limit above this, bigger datasets go through the exports
+// endpoint which returns every record at once
+const RECORDS_WINDOW: usize = 10000;
+const MAX_RETRIES: u32 = 5;
+
+#[derive(Deserialize, Debug)]
+pub struct Page<T> {
+    pub total_count: usize,
+    pub results: Vec<T>,
+}
+
+#[derive(Debug)]
+pub enum OpenDataSoftError {
+    Url(String),
+    Reqwest(String),
+    Status(StatusCode, String),
+    RateLimited(String),
+    Json(String),
+}
+
+impl Display for OpenDataSoftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenDataSoftError::Url(message) => write!(f, "invalid opendatasoft url: {}", message),
+            OpenDataSoftError::Reqwest(message) => write!(f, "reqwest error: {}", message),
+            OpenDataSoftError::Status(status, body) => {
+                write!(f, "opendatasoft answered {}: {}", status, body)
+            }
+            OpenDataSoftError::RateLimited(url) => {
+                write!(f, "still rate limited after {} retries: {}", MAX_RETRIES, url)
+            }
+            OpenDataSoftError::Json(message) => write!(f, "serde_json error: {}", message),
+        }
+    }
+}
+
+// records query on a dataset, see the ODSQL documentation for the syntax of select and where
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub dataset: String,
+    pub select: Option<String>,
+    pub conditions: Vec<String>,
+    pub refines: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn new(dataset: &str) -> Self {
+        Self {
+            dataset: dataset.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn select(mut self, select: &str) -> Self {
+        self.select = Some(select.to_string());
+        self
+    }
+
+    // conditions are joined with AND
+    pub fn filter(mut self, condition: &str) -> Self {
+        self.conditions.push(condition.to_string());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn refine(mut self, facet: &str, value: &str) -> Self {
+        self.refines.push((facet.to_string(), value.to_string()));
+        self
+    }
+
+    fn url(&self, base_url: &str, endpoint: &str) -> Result<Url, OpenDataSoftError> {
+        let mut url = Url::parse(base_url).map_err(|e| OpenDataSoftError::Url(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_| OpenDataSoftError::Url(base_url.to_string()))?
+            .pop_if_empty()
+            .extend(["api", "explore", "v2.1", "catalog", "datasets", &self.dataset])
+            .extend(endpoint.split('/'));
+        {
+            let mut pairs = url.query_pairs_mut();
+            if let Some(select) = &self.select {
+                pairs.append_pair("select", select);
+            }
+            if !self.conditions.is_empty() {
+                let condition = self
+                    .conditions
+                    .iter()
+                    .map(|condition| format!("({})", condition))
+                    .collect::<Vec<_>>()
+                    .join(" AND ");
+                pairs.append_pair("where", &condition);
+            }
+            for (facet, value) in self.refines.iter() {
+                pairs.append_pair("refine", &format!("{}:{}", facet, value));
+            }
+        }
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+        Ok(url)
+    }
+
+    fn page_url(&self, base_url: &str, offset: usize) -> Result<Url, OpenDataSoftError> {
+        let mut url = self.url(base_url, "records")?;
+        url.query_pairs_mut()
+            .append_pair("limit", &PAGE_SIZE.to_string())
+            .append_pair("offset", &offset.to_string());
+        Ok(url)
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl Client {
+    // base_url is the portal, e.g. https://www.herault-data.fr
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    // fetches every record matching the query, page after page until total_count is reached
+    pub async fn records<T: DeserializeOwned>(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<T>, OpenDataSoftError> {
+        let first: Page<T> = self.get(query.page_url(&self.base_url, 0)?).await?;
+        let total_count = first.total_count;
+        if total_count > RECORDS_WINDOW {
+            info!(
+                "{}: {} records, more than the records endpoint serves, exporting them",
+                query.dataset, total_count
+            );
+            return self.get(query.url(&self.base_url, "exports/json")?).await;
+        }
+
+        let mut records = first.results;
+        while records.len() < total_count {
+            let page: Page<T> = self.get(query.page_url(&self.base_url, records.len())?).await?;
+            if page.results.is_empty() {
+                warn!(
+                    "{}: empty page at offset {} of {} records",
+                    query.dataset,
+                    records.len(),
+                    total_count
+                );
+                break;
+            }
+            records.extend(page.results);
+        }
+        info!("{}: {} records fetched", query.dataset, records.len());
+        Ok(records)
+    }
+
+    // GET with retries when the portal rate limits us (429) or is briefly unavailable (503),
+    // waiting for Retry-After when given, with an exponential backoff otherwise
+    async fn get<R: DeserializeOwned>(&self, url: Url) -> Result<R, OpenDataSoftError> {
+        for attempt in 0..=MAX_RETRIES {
+            let response = self
+                .http
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(|e| OpenDataSoftError::Reqwest(e.to_string()))?;
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+                let wait = retry_after(response.headers())
+                    .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+                warn!("{} on {}, retrying in {:?}", status, url, wait);
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            let body = response
+                .text()
+                .await
+                .map_err(|e| OpenDataSoftError::Reqwest(e.to_string()))?;
+            if !status.is_success() {
+                return Err(OpenDataSoftError::Status(status, body));
+            }
+            return serde_json::from_str(&body).map_err(|e| OpenDataSoftError::Json(e.to_string()));
+        }
+        Err(OpenDataSoftError::RateLimited(url.to_string()))
+    }
+}
+
+// Retry-After in seconds. Explore also sends X-RateLimit-Reset, but only with its quota headers
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_url() {
+        let query = Query::new("onisep-etablissements-denseignement-superieur-herault")
+            .select("code_uai,nom")
+            .filter("statut = 'Public'")
+            .filter("commune = 'Montpellier'")
+            .refine("type_d_etablissement", "université");
+        let url = query.page_url("https://www.herault-data.fr/", 200).unwrap();
+        assert_eq!(url.path(), "/api/explore/v2.1/catalog/datasets/onisep-etablissements-denseignement-superieur-herault/records");
+        let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("select".to_string(), "code_uai,nom".to_string()),
+                (
+                    "where".to_string(),
+                    "(statut = 'Public') AND (commune = 'Montpellier')".to_string()
+                ),
+                ("refine".to_string(), "type_d_etablissement:université".to_string()),
+                ("limit".to_string(), "100".to_string()),
+                ("offset".to_string(), "200".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_url() {
+        let url = Query::new("dataset")
+            .url("https://data.enseignementsup-recherche.gouv.fr", "exports/json")
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://data.enseignementsup-recherche.gouv.fr/api/explore/v2.1/catalog/datasets/dataset/exports/json"
+        );
+    }
+}
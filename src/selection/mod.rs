@@ -0,0 +1,199 @@
+use serde::Deserialize;
+
+use crate::{
+    cli::actions::schools::ApiSchool,
+    conf::{self, ConfError},
+    text::fold,
+};
+
+const DEFAULT_SELECTION: &str = include_str!("../../conf/schools.json");
+
+pub const ALLOW_UAI: &str = "allow-uai";
+pub const DENY_UAI: &str = "deny-uai";
+pub const ALL_RULES: &str = "all-rules";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Status,
+    Type,
+    Tutelle,
+    University,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RuleDefinition {
+    pub id: String,
+    pub field: Field,
+    // the field has to contain one of these, when given
+    #[serde(default)]
+    pub include: Vec<String>,
+    // the field must not contain any of these
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SelectionFile {
+    pub version: u32,
+    #[serde(default)]
+    pub allow_uai: Vec<String>,
+    #[serde(default)]
+    pub deny_uai: Vec<String>,
+    pub rules: Vec<RuleDefinition>,
+}
+
+// why a school was kept or dropped: the UAI lists, the first rule it failed, or all the rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub kept: bool,
+    pub rule: String,
+}
+
+pub struct Selection {
+    version: u32,
+    allow_uai: Vec<String>,
+    deny_uai: Vec<String>,
+    rules: Vec<RuleDefinition>,
+}
+
+impl Selection {
+    pub fn load() -> Result<Self, ConfError> {
+        Self::from_json(&conf::load("SCHOOL_RULES", DEFAULT_SELECTION)?)
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, ConfError> {
+        Ok(Self::new(conf::parse(raw)?))
+    }
+
+    fn new(file: SelectionFile) -> Self {
+        let normalize_uai = |codes: Vec<String>| {
+            codes
+                .into_iter()
+                .map(|code| code.trim().to_uppercase())
+                .collect::<Vec<_>>()
+        };
+        let normalize_patterns = |patterns: Vec<String>| {
+            patterns.iter().map(|pattern| fold(pattern.trim())).collect::<Vec<_>>()
+        };
+        Self {
+            version: file.version,
+            allow_uai: normalize_uai(file.allow_uai),
+            deny_uai: normalize_uai(file.deny_uai),
+            rules: file
+                .rules
+                .into_iter()
+                .map(|rule| RuleDefinition {
+                    include: normalize_patterns(rule.include),
+                    exclude: normalize_patterns(rule.exclude),
+                    ..rule
+                })
+                .collect(),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // ids of every rule a verdict can name, in evaluation order
+    pub fn rule_ids(&self) -> Vec<&str> {
+        let mut ids = vec![ALLOW_UAI, DENY_UAI];
+        ids.extend(self.rules.iter().map(|rule| rule.id.as_str()));
+        ids.push(ALL_RULES);
+        ids
+    }
+
+    pub fn evaluate(&self, school: &ApiSchool) -> Verdict {
+        let code_uai = school.code_uai.as_deref().unwrap_or_default().trim().to_uppercase();
+        if self.allow_uai.contains(&code_uai) {
+            return Verdict {
+                kept: true,
+                rule: ALLOW_UAI.to_string(),
+            };
+        }
+        if self.deny_uai.contains(&code_uai) {
+            return Verdict {
+                kept: false,
+                rule: DENY_UAI.to_string(),
+            };
+        }
+        for rule in self.rules.iter() {
+            let value = match rule.field {
+                Field::Status => school.statut.as_deref(),
+                Field::Type => school.type_d_etablissement.as_deref(),
+                Field::Tutelle => school.tutelle.as_deref(),
+                Field::University => school.universite.as_deref(),
+            }
+            .map(fold)
+            .unwrap_or_default();
+            let included = rule.include.is_empty()
+                || rule.include.iter().any(|pattern| value.contains(pattern.as_str()));
+            let excluded = rule.exclude.iter().any(|pattern| value.contains(pattern.as_str()));
+            if !included || excluded {
+                return Verdict {
+                    kept: false,
+                    rule: rule.id.clone(),
+                };
+            }
+        }
+        Verdict {
+            kept: true,
+            rule: ALL_RULES.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn school(code_uai: &str, statut: &str, type_d_etablissement: &str) -> ApiSchool {
+        ApiSchool {
+            code_uai: Some(code_uai.to_string()),
+            statut: Some(statut.to_string()),
+            type_d_etablissement: Some(type_d_etablissement.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_selection_keeps_public_schools() {
+        let selection = Selection::from_json(DEFAULT_SELECTION).unwrap();
+        assert!(selection.evaluate(&school("0341234A", "Public", "Université")).kept);
+        assert_eq!(
+            selection.evaluate(&school("0341234A", "Privé hors contrat", "École de commerce")),
+            Verdict {
+                kept: false,
+                rule: "public".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_selection_rules() {
+        let selection = Selection::from_json(
+            r#"{
+                "version": 1,
+                "allow_uai": ["0349999z"],
+                "deny_uai": ["0341111A"],
+                "rules": [
+                    {"id": "status", "field": "status", "include": ["public", "privé sous contrat"]},
+                    {"id": "no-lycee", "field": "type", "exclude": ["lycée"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let verdict = |school: ApiSchool| selection.evaluate(&school);
+        assert_eq!(verdict(school("0349999Z", "Privé hors contrat", "Lycée")).rule, ALLOW_UAI);
+        assert_eq!(verdict(school("0341111A", "Public", "Université")).rule, DENY_UAI);
+        assert_eq!(verdict(school("0341000A", "Prive sous contrat", "Lycee")).rule, "no-lycee");
+        assert_eq!(
+            verdict(school("0341000A", "Privé sous contrat", "École d'ingénieurs")),
+            Verdict {
+                kept: true,
+                rule: ALL_RULES.to_string()
+            }
+        );
+    }
+}
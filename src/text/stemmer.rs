@@ -0,0 +1,90 @@
+#[cfg(feature = "stemming")]
+use std::sync::Arc;
+
+use super::fold;
+
+// french stemmer used by the keyword pipeline so that "frites" and "frite", or "râpées" and
+// "râpé" share the same stem. Words are folded before being stemmed, so that a query typed
+// without accents gets the same stem as the dish it is looking for
+#[derive(Clone)]
+pub struct Stemmer {
+    #[cfg(feature = "stemming")]
+    inner: Arc<rust_stemmers::Stemmer>,
+}
+
+impl Default for Stemmer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stemmer {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "stemming")]
+            inner: Arc::new(rust_stemmers::Stemmer::create(rust_stemmers::Algorithm::French)),
+        }
+    }
+
+    // stems every word of a term
+    pub fn stem(&self, term: &str) -> String {
+        term.split_whitespace()
+            .map(|word| self.stem_word(&fold(word)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // the same snowball stemmer on the same folded word as the public.french_unaccent search
+    // configuration, so that a stem is also a lexeme of the Postgres full-text index
+    #[cfg(feature = "stemming")]
+    fn stem_word(&self, word: &str) -> String {
+        self.inner.stem(word).into_owned()
+    }
+
+    #[cfg(not(feature = "stemming"))]
+    fn stem_word(&self, word: &str) -> String {
+        word.to_string()
+    }
+}
+
+#[cfg(all(test, feature = "stemming"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem_variants() {
+        let stemmer = Stemmer::new();
+        assert_eq!(stemmer.stem("Frites"), stemmer.stem("frite"));
+        assert_eq!(stemmer.stem("carottes"), stemmer.stem("carotte"));
+        // without its accents "rapees" keeps an "e", a query on "râpé" still finds it as a prefix
+        assert!(stemmer.stem("râpées").starts_with(&stemmer.stem("râpé")));
+        assert_eq!(stemmer.stem("carottes râpées"), stemmer.stem("carotte rapee"));
+    }
+
+    #[test]
+    fn test_stem_as_postgres() {
+        // lexemes of SELECT to_tsvector('public.french_unaccent', word)
+        let lexemes = [
+            ("Frites", "frit"),
+            ("carottes", "carott"),
+            ("râpées", "rape"),
+            ("râpé", "rap"),
+            ("lasagnes", "lasagn"),
+            ("végétariennes", "vegetarien"),
+            ("végé", "veg"),
+            ("échalote", "echalot"),
+            ("œufs", "oeuf"),
+            ("rôti", "rot"),
+            ("crème", "crem"),
+            ("brûlée", "brule"),
+            ("boulettes", "boulet"),
+            ("purée", "pure"),
+            ("Richter", "richt"),
+            ("cafétéria", "cafeteri"),
+        ];
+        let stemmer = Stemmer::new();
+        for (word, lexeme) in lexemes {
+            assert_eq!(stemmer.stem(word), lexeme, "{}", word);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// French stopwords in their folded form (see `text::fold`), plus a few words that CROUS menus
+// use as filler ("façon", "selon arrivage", "au choix")
+const STOPWORDS: &[&str] = &[
+    "a", "ai", "au", "aux", "avec", "c", "ce", "ces", "cet", "cette", "d", "dans", "de", "des",
+    "du", "elle", "en", "et", "est", "il", "j", "je", "l", "la", "le", "les", "leur", "lui", "m",
+    "ma", "mais", "me", "mes", "moi", "mon", "n", "ne", "ni", "nos", "notre", "nous", "on", "ou",
+    "par", "pas", "pour", "qu", "que", "qui", "s", "sa", "sans", "se", "ses", "son", "sur", "t",
+    "ta", "te", "tes", "toi", "ton", "tu", "un", "une", "vos", "votre", "vous", "y", "facon",
+    "selon", "arrivage", "choix",
+];
+
+// words users type in a search ("près de Triolet") that mean nothing in a restaurant or a dish.
+// They are only dropped from queries, "Vers" or "Autour" may still be part of a name
+const SEARCH_STOPWORDS: &[&str] = &["pres", "proche", "autour", "vers"];
+
+pub fn is_stopword(folded: &str) -> bool {
+    STOPWORDS.contains(&folded)
+}
+
+pub fn is_search_stopword(folded: &str) -> bool {
+    SEARCH_STOPWORDS.contains(&folded)
+}
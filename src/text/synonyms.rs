@@ -0,0 +1,136 @@
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::fold;
+use crate::conf::{self, ConfError};
+
+const DEFAULT_SYNONYMS: &str = include_str!("../../conf/synonyms.json");
+
+#[derive(Deserialize, Debug)]
+pub struct SynonymsFile {
+    pub version: u32,
+    pub synonyms: Vec<SynonymDefinition>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SynonymDefinition {
+    pub alias: String,
+    pub canonical: String,
+    // url of the restaurant the alias designates, if any
+    #[serde(default)]
+    pub restaurant: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Synonym {
+    pub alias: String,
+    pub canonical: String,
+    pub restaurant: Option<String>,
+    folded_alias: String,
+    folded_canonical: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Synonyms {
+    version: u32,
+    entries: Vec<Synonym>,
+}
+
+impl Synonyms {
+    pub fn load() -> Result<Self, ConfError> {
+        Self::from_json(&conf::load("SYNONYMS", DEFAULT_SYNONYMS)?)
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, ConfError> {
+        Ok(Self::new(conf::parse(raw)?))
+    }
+
+    fn new(file: SynonymsFile) -> Self {
+        let mut entries: Vec<Synonym> = file
+            .synonyms
+            .into_iter()
+            .map(|definition| Synonym {
+                folded_alias: normalize(&definition.alias),
+                folded_canonical: normalize(&definition.canonical),
+                alias: definition.alias,
+                canonical: definition.canonical,
+                restaurant: definition.restaurant,
+            })
+            .collect();
+        // longest aliases first, so "resto univ" is expanded before "resto"
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.folded_alias.len()));
+        Self {
+            version: file.version,
+            entries,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // entries concerning a text: the ones whose alias or canonical form appear in it, and the
+    // ones tied to the restaurant the text comes from
+    pub fn matching(&self, text: &str, restaurant_url: Option<&str>) -> Vec<&Synonym> {
+        let text = format!(" {} ", normalize(text));
+        self.entries
+            .iter()
+            .filter(|entry| match &entry.restaurant {
+                Some(url) => restaurant_url == Some(url.as_str()),
+                None => {
+                    text.contains(&format!(" {} ", entry.folded_alias))
+                        || text.contains(&format!(" {} ", entry.folded_canonical))
+                }
+            })
+            .collect()
+    }
+
+    // rewrites every alias of a query to its canonical form, e.g. "ru richter" becomes
+    // "resto u richter"
+    pub fn expand(&self, query: &str) -> String {
+        let mut expanded = format!(" {} ", normalize(query));
+        for entry in self.entries.iter() {
+            let alias = format!(" {} ", entry.folded_alias);
+            if expanded.contains(&alias) {
+                expanded = expanded.replace(&alias, &format!(" {} ", entry.folded_canonical));
+            }
+        }
+        expanded.trim().to_string()
+    }
+}
+
+// folded words of a text separated by single spaces
+fn normalize(text: &str) -> String {
+    fold(text).unicode_words().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let synonyms = Synonyms::from_json(DEFAULT_SYNONYMS).unwrap();
+        assert_eq!(synonyms.expand("RU Richter"), "resto u richter");
+        assert_eq!(synonyms.expand("cafet triolet"), "cafeteria triolet");
+        assert_eq!(synonyms.expand("lasagnes"), "lasagnes");
+    }
+
+    #[test]
+    fn test_matching() {
+        let synonyms = Synonyms::from_json(DEFAULT_SYNONYMS).unwrap();
+        let aliases = synonyms
+            .matching("Cafétéria Triolet", None)
+            .iter()
+            .map(|entry| entry.alias.clone())
+            .collect::<Vec<_>>();
+        assert!(aliases.contains(&"cafet".to_string()));
+        assert!(!aliases.contains(&"ru".to_string()));
+
+        let tied = synonyms.matching(
+            "Resto U' Triolet",
+            Some("https://www.crous-montpellier.fr/restaurant/resto-u-triolet/"),
+        );
+        assert!(tied.iter().any(|entry| entry.alias == "fac des sciences"));
+    }
+}
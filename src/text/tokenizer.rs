@@ -0,0 +1,101 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{fold, stemmer::Stemmer, stopwords::is_stopword};
+
+const MIN_LENGTH: usize = 3;
+const MIN_ACRONYM_LENGTH: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    // lowercased word, with its accents, used to display a suggestion
+    pub display: String,
+    // folded word, used to match a suggestion
+    pub folded: String,
+    // stem of the word, used to match plural and gender variants
+    pub stem: String,
+}
+
+#[derive(Clone, Default)]
+pub struct Tokenizer {
+    stemmer: Stemmer,
+}
+
+impl Tokenizer {
+    pub fn new() -> Self {
+        Self {
+            stemmer: Stemmer::new(),
+        }
+    }
+
+    pub fn stemmer(&self) -> &Stemmer {
+        &self.stemmer
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut tokens: Vec<Token> = Vec::new();
+        for word in text.unicode_words() {
+            // "l'entrecôte" is a single word for UAX #29, the elided article is dropped here
+            let word = word.rsplit(['\'', '’']).next().unwrap_or(word);
+            let folded = fold(word);
+            if !self.keep(word, &folded) {
+                continue;
+            }
+            if tokens.iter().any(|token| token.folded == folded) {
+                continue;
+            }
+            tokens.push(Token {
+                display: word.to_lowercase(),
+                stem: self.stemmer.stem(word),
+                folded,
+            });
+        }
+        tokens
+    }
+
+    fn keep(&self, word: &str, folded: &str) -> bool {
+        if is_stopword(folded) || folded.chars().all(|c| c.is_numeric()) {
+            return false;
+        }
+        let length = folded.chars().count();
+        if length >= MIN_LENGTH {
+            return true;
+        }
+        // acronyms such as "RU" are kept even though they are short
+        length >= MIN_ACRONYM_LENGTH && word.chars().all(|c| c.is_uppercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(text: &str) -> Vec<String> {
+        Tokenizer::new()
+            .tokenize(text)
+            .into_iter()
+            .map(|token| token.folded)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_restaurant_name() {
+        assert_eq!(folded("Cafétéria de la Lironde - Sète"), vec!["cafeteria", "lironde", "sete"]);
+        assert_eq!(folded("Resto U' Richter"), vec!["resto", "richter"]);
+        assert_eq!(folded("RU Triolet"), vec!["ru", "triolet"]);
+        // search stopwords are only dropped from queries
+        assert_eq!(folded("Brasserie Autour du Lac"), vec!["brasserie", "autour", "lac"]);
+    }
+
+    #[test]
+    fn test_tokenize_dish() {
+        let tokens = Tokenizer::new().tokenize("Carottes râpées à l'échalote, 2 œufs");
+        assert_eq!(
+            tokens.iter().map(|token| token.display.as_str()).collect::<Vec<_>>(),
+            vec!["carottes", "râpées", "échalote", "œufs"]
+        );
+        assert_eq!(
+            tokens.iter().map(|token| token.folded.as_str()).collect::<Vec<_>>(),
+            vec!["carottes", "rapees", "echalote", "oeufs"]
+        );
+    }
+}